@@ -124,6 +124,9 @@ pub const MDB_APPEND: c_uint = 0x20000;
 pub const MDB_APPENDDUP: c_uint = 0x40000;
 pub const MDB_MULTIPLE: c_uint = 0x80000;
 
+// Copy flags
+pub const MDB_CP_COMPACT: c_uint = 0x01;
+
 // Database flags
 pub const MDB_REVERSEKEY: c_uint = 0x02;
 pub const MDB_DUPSORT: c_uint = 0x04;
@@ -154,6 +157,8 @@ extern "C" {
     pub fn mdb_env_open(env: *mut MDB_env, path: *const c_char, flags: c_uint, mode: mdb_mode_t) -> c_int;
     pub fn mdb_env_copy(env: *mut MDB_env, path: *const c_char) -> c_int;
     pub fn mdb_env_copyfd(env: *mut MDB_env, fd: mdb_filehandle_t) -> c_int;
+    pub fn mdb_env_copy2(env: *mut MDB_env, path: *const c_char, flags: c_uint) -> c_int;
+    pub fn mdb_env_copyfd2(env: *mut MDB_env, fd: mdb_filehandle_t, flags: c_uint) -> c_int;
     pub fn mdb_env_stat(env: *mut MDB_env, stat: *mut MDB_stat) -> c_int;
     pub fn mdb_env_info(env: *mut MDB_env, info: *mut MDB_envinfo) -> c_int;
     pub fn mdb_env_sync(env: *mut MDB_env, force: c_int) -> c_int;
@@ -169,6 +174,7 @@ extern "C" {
     pub fn mdb_env_get_maxkeysize(env: *mut MDB_env) -> c_int;
     pub fn mdb_txn_begin(env: *mut MDB_env, parent: *mut MDB_txn, flags: c_uint, txn: *mut *mut MDB_txn) -> c_int;
     pub fn mdb_txn_env(txn: *mut MDB_txn) -> *mut MDB_env;
+    pub fn mdb_txn_id(txn: *mut MDB_txn) -> size_t;
     pub fn mdb_txn_commit(txn: *mut MDB_txn) -> c_int;
     pub fn mdb_txn_abort(txn: *mut MDB_txn);
     pub fn mdb_txn_reset(txn: *mut MDB_txn);
@@ -0,0 +1,79 @@
+//! Ready-made key comparators for integer keys, to be used with
+//! [set_compare](../database/struct.Database.html#method.set_compare)
+//! or [set_int_compare](../database/struct.Database.html#method.set_int_compare)
+//!
+//! Keys of unexpected size are ordered by size first, so that a
+//! stray key doesn't break ordering of the rest.
+
+use libc::c_int;
+use std::cmp::Ordering;
+use std::slice;
+
+use ffi::MDB_val;
+
+/// Integer key layouts supported by `Database::set_int_compare`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IntWidth {
+    /// `u32` stored in big-endian order
+    U32Be,
+    /// `u64` stored in big-endian order
+    U64Be,
+    /// `i64` stored in native order
+    I64Native,
+}
+
+impl IntWidth {
+    /// Comparator for this layout
+    pub fn comparator(self) -> extern "C" fn(*const MDB_val, *const MDB_val) -> c_int {
+        match self {
+            IntWidth::U32Be => cmp_u32_be,
+            IntWidth::U64Be => cmp_u64_be,
+            IntWidth::I64Native => cmp_i64_native,
+        }
+    }
+}
+
+fn as_bytes<'a>(val: *const MDB_val) -> &'a [u8] {
+    unsafe { slice::from_raw_parts((*val).mv_data as *const u8, (*val).mv_size) }
+}
+
+fn compare<T: Ord, F: Fn(&[u8]) -> T>(a: *const MDB_val, b: *const MDB_val, size: usize, read: F) -> c_int {
+    let (a, b) = (as_bytes(a), as_bytes(b));
+    let ord = if a.len() == size && b.len() == size {
+        read(a).cmp(&read(b))
+    } else {
+        a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+    };
+    match ord {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }
+}
+
+/// Compares keys as big-endian `u32`
+pub extern "C" fn cmp_u32_be(a: *const MDB_val, b: *const MDB_val) -> c_int {
+    compare(a, b, 4, |bytes| {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(bytes);
+        u32::from_be_bytes(buf)
+    })
+}
+
+/// Compares keys as big-endian `u64`
+pub extern "C" fn cmp_u64_be(a: *const MDB_val, b: *const MDB_val) -> c_int {
+    compare(a, b, 8, |bytes| {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(bytes);
+        u64::from_be_bytes(buf)
+    })
+}
+
+/// Compares keys as native-endian `i64`, i.e. negative keys go first
+pub extern "C" fn cmp_i64_native(a: *const MDB_val, b: *const MDB_val) -> c_int {
+    compare(a, b, 8, |bytes| {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(bytes);
+        i64::from_ne_bytes(buf)
+    })
+}
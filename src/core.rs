@@ -45,7 +45,10 @@ use std::result::Result;
 use std::mem;
 use ffi::{self, MDB_val};
 pub use MdbError::{NotFound, KeyExists, Other, StateError, Corrupted, Panic};
-pub use MdbError::{InvalidPath, TxnFull, CursorFull, PageFull, CacheError};
+pub use MdbError::{InvalidPath, TxnFull, CursorFull, PageFull, CacheError, Conversion, MapFull, AppendOutOfOrder, WriteLimit};
+pub use MdbError::{PageNotFound, VersionMismatch, Invalid, DbsFull, ReadersFull, TlsFull};
+pub use MdbError::{MapResized, Incompatible, BadRslot, BadTxn, BadValSize};
+use crate::traits::TryFromMdbValue;
 use crate::utils::{error_msg};
 
 macro_rules! lift_mdb {
@@ -76,9 +79,7 @@ macro_rules! assert_state_eq {
         ({
             let c = $cur;
             let e = $exp;
-            if c == e {
-                ()
-            } else {
+            if c != e {
                 let msg = format!("{} requires {:?}, is in {:?}", stringify!($log), c, e);
                 return Err(StateError(msg))
             }})
@@ -97,6 +98,25 @@ pub enum MdbError {
     InvalidPath,
     StateError(String),
     CacheError,
+    Conversion(String),
+    MapFull(String),
+    /// Key passed to `append` isn't greater than the last key,
+    /// reported by LMDB as `MDB_KEYEXIST`
+    AppendOutOfOrder(String),
+    /// Soft write limit set by `Transaction::set_write_limit` would be
+    /// exceeded, unlike `TxnFull` transaction is still usable
+    WriteLimit(String),
+    PageNotFound,
+    VersionMismatch,
+    Invalid,
+    DbsFull,
+    ReadersFull,
+    TlsFull,
+    MapResized,
+    Incompatible,
+    BadRslot,
+    BadTxn,
+    BadValSize,
     Other(c_int, String)
 }
 
@@ -111,6 +131,18 @@ impl MdbError {
             ffi::MDB_PAGE_FULL   => PageFull,
             ffi::MDB_CORRUPTED   => Corrupted,
             ffi::MDB_PANIC       => Panic,
+            ffi::MDB_MAP_FULL    => MapFull(error_msg(code)),
+            ffi::MDB_PAGE_NOTFOUND    => PageNotFound,
+            ffi::MDB_VERSION_MISMATCH => VersionMismatch,
+            ffi::MDB_INVALID     => Invalid,
+            ffi::MDB_DBS_FULL    => DbsFull,
+            ffi::MDB_READERS_FULL => ReadersFull,
+            ffi::MDB_TLS_FULL    => TlsFull,
+            ffi::MDB_MAP_RESIZED => MapResized,
+            ffi::MDB_INCOMPATIBLE => Incompatible,
+            ffi::MDB_BAD_RSLOT   => BadRslot,
+            ffi::MDB_BAD_TXN     => BadTxn,
+            ffi::MDB_BAD_VALSIZE => BadValSize,
             _                    => Other(code, error_msg(code))
         }
     }
@@ -122,8 +154,15 @@ impl std::fmt::Display for MdbError {
         match &self {
             NotFound | KeyExists | TxnFull |
             CursorFull | PageFull | Corrupted |
-            Panic | InvalidPath | CacheError => write!(fmt, "{}", self.description()),
+            Panic | InvalidPath | CacheError |
+            PageNotFound | VersionMismatch | Invalid |
+            DbsFull | ReadersFull | TlsFull | MapResized |
+            Incompatible | BadRslot | BadTxn | BadValSize => write!(fmt, "{}", self.description()),
             StateError(ref msg) => write!(fmt, "{}", msg),
+            Conversion(ref msg) => write!(fmt, "{}", msg),
+            MapFull(ref msg) => write!(fmt, "{}", msg),
+            AppendOutOfOrder(ref msg) => write!(fmt, "{}", msg),
+            WriteLimit(ref msg) => write!(fmt, "{}", msg),
             Other(code, ref msg) => write!(fmt, "{}: {}", code, msg)
         }
     }
@@ -142,6 +181,21 @@ impl Error for MdbError {
             InvalidPath => "invalid path for database",
             StateError(_) => "state error",
             CacheError => "db cache error",
+            Conversion(_) => "conversion error",
+            MapFull(_) => "map full",
+            AppendOutOfOrder(_) => "append out of order",
+            WriteLimit(_) => "write limit reached",
+            PageNotFound => "requested page not found",
+            VersionMismatch => "database version mismatch",
+            Invalid => "file is not a valid LMDB file",
+            DbsFull => "max named databases reached",
+            ReadersFull => "max readers reached",
+            TlsFull => "thread-local storage keys full",
+            MapResized => "map was resized by another process",
+            Incompatible => "operation incompatible with database",
+            BadRslot => "invalid reuse of reader locktable slot",
+            BadTxn => "transaction must abort",
+            BadValSize => "unsupported size of key or data",
             Other(_, _) => "other error",
         }
     }
@@ -150,6 +204,98 @@ impl Error for MdbError {
 
 pub type MdbResult<T> = Result<T, MdbError>;
 
+/// Readable wrapper of environment or database statistics
+pub struct Stat(pub ffi::MDB_stat);
+
+impl std::fmt::Display for Stat {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(fmt, "page size: {}", self.0.ms_psize)?;
+        writeln!(fmt, "tree depth: {}", self.0.ms_depth)?;
+        writeln!(fmt, "branch pages: {}", self.0.ms_branch_pages)?;
+        writeln!(fmt, "leaf pages: {}", self.0.ms_leaf_pages)?;
+        writeln!(fmt, "overflow pages: {}", self.0.ms_overflow_pages)?;
+        write!(fmt, "entries: {}", self.0.ms_entries)
+    }
+}
+
+impl std::fmt::Debug for Stat {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("Stat")
+            .field("ms_psize", &self.0.ms_psize)
+            .field("ms_depth", &self.0.ms_depth)
+            .field("ms_branch_pages", &self.0.ms_branch_pages)
+            .field("ms_leaf_pages", &self.0.ms_leaf_pages)
+            .field("ms_overflow_pages", &self.0.ms_overflow_pages)
+            .field("ms_entries", &self.0.ms_entries)
+            .finish()
+    }
+}
+
+/// Readable wrapper of environment information
+pub struct EnvInfo(pub ffi::MDB_envinfo);
+
+impl std::fmt::Display for EnvInfo {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(fmt, "map address: {:p}", self.0.me_mapaddr)?;
+        writeln!(fmt, "map size: {}", self.0.me_mapsize)?;
+        writeln!(fmt, "last page: {}", self.0.me_last_pgno)?;
+        writeln!(fmt, "last txnid: {}", self.0.me_last_txnid)?;
+        writeln!(fmt, "max readers: {}", self.0.me_maxreaders)?;
+        write!(fmt, "readers used: {}", self.0.me_numreaders)
+    }
+}
+
+impl std::fmt::Debug for EnvInfo {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("EnvInfo")
+            .field("me_mapaddr", &self.0.me_mapaddr)
+            .field("me_mapsize", &self.0.me_mapsize)
+            .field("me_last_pgno", &self.0.me_last_pgno)
+            .field("me_last_txnid", &self.0.me_last_txnid)
+            .field("me_maxreaders", &self.0.me_maxreaders)
+            .field("me_numreaders", &self.0.me_numreaders)
+            .finish()
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Marks plain data types which could be stored as their memory
+/// representation, see `MdbValue::from_pod`. Sealed, implemented
+/// only for primitives
+pub trait Pod: Copy + sealed::Sealed {}
+
+macro_rules! pod {
+    ($($t:ty),*) => ($(
+        impl sealed::Sealed for $t {}
+        impl Pod for $t {}
+    )*)
+}
+
+pod!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, f32, f64, bool);
+pod!(std::num::NonZeroU32, std::num::NonZeroU64, std::num::NonZeroU128);
+
+/// Plain integers and floats, for which every bit pattern is a valid
+/// value, so they could be read in place from database pages, see
+/// `Cursor::get_multiple`. Sealed, unlike `Pod` excludes `bool` and
+/// `NonZero*`
+///
+/// ```compile_fail
+/// fn plain<T: lmdb_rs_et::Plain>() {}
+/// plain::<bool>();
+/// ```
+pub trait Plain: Pod {}
+
+macro_rules! plain {
+    ($($t:ty),*) => ($(
+        impl Plain for $t {}
+    )*)
+}
+
+plain!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, f32, f64);
+
 #[derive(Copy, Clone, Debug)]
 pub struct MdbValue<'a> {
     pub value: MDB_val,
@@ -174,12 +320,27 @@ impl<'a> MdbValue<'a> {
     }
 
     #[inline]
+    #[deprecated(note = "use `from_pod`, arbitrary types may contain padding or pointers")]
     pub fn new_from_sized<T>(data: &'a T) -> MdbValue<'a> {
         unsafe {
             MdbValue::new(data as *const T as *const libc::c_void, mem::size_of::<T>())
         }
     }
 
+    /// Wraps a plain value, i.e. a primitive without padding or
+    /// pointers, which is safe to store as its memory representation
+    ///
+    /// ```compile_fail
+    /// let data = String::from("not plain");
+    /// lmdb_rs_et::MdbValue::from_pod(&data);
+    /// ```
+    #[inline]
+    pub fn from_pod<T: Pod>(data: &'a T) -> MdbValue<'a> {
+        unsafe {
+            MdbValue::new(data as *const T as *const libc::c_void, mem::size_of::<T>())
+        }
+    }
+
     #[inline]
     pub unsafe fn get_ref(&'a self) -> *const c_void {
         self.value.mv_data
@@ -190,3 +351,19 @@ impl<'a> MdbValue<'a> {
         self.value.mv_size as usize
     }
 }
+
+macro_rules! checked_as {
+    ($($name:ident -> $t:ty),*) => (
+        impl<'a> MdbValue<'a> {
+            $(
+                /// Reads value as an integer in native byte order,
+                /// fails with `Conversion` if size doesn't match
+                pub fn $name(&self) -> MdbResult<$t> {
+                    TryFromMdbValue::try_from_mdb_value(self)
+                }
+            )*
+        }
+    )
+}
+
+checked_as!(as_u16 -> u16, as_i16 -> i16, as_u32 -> u32, as_i32 -> i32, as_u64 -> u64, as_i64 -> i64);
@@ -1,13 +1,17 @@
 use libc::{c_uint, size_t};
 use std;
+use std::cell::Cell;
 use std::cmp::{Ordering};
+use std::iter::{FilterMap, Map};
+use std::ops::Bound;
 use std::ptr;
 use std::mem;
 use ffi::{self};
 use crate::traits::{ToMdbValue, FromMdbValue};
 
 use crate::transaction::{ Txn };
-use crate::core::{ MdbError, MdbResult, MdbValue };
+use crate::core::{ MdbError, MdbResult, MdbValue, Plain, StateError };
+use crate::database::{ Database, DB_ALLOW_DUPS, DB_DUP_FIXED };
 
 /// Helper to determine the property of "less than or equal to" where
 /// the "equal to" part is to be specified at runtime.
@@ -43,6 +47,7 @@ pub struct Cursor<'c, 'txn> {
     db: ffi::MDB_dbi,
     valid_key: bool,
     valid_value: bool,
+    positioned: bool,
 }
 
 impl<'c, 'txn> Cursor<'c, 'txn> {
@@ -58,6 +63,7 @@ impl<'c, 'txn> Cursor<'c, 'txn> {
             db,
             valid_key: false,
             valid_value: false,
+            positioned: false,
         })
     }
 
@@ -68,6 +74,7 @@ impl<'c, 'txn> Cursor<'c, 'txn> {
         let res = unsafe {
             ffi::mdb_cursor_get(self.handle, &mut self.key_val, &mut self.data_val, op)
         };
+        self.positioned = res == ffi::MDB_SUCCESS;
         match res {
             ffi::MDB_SUCCESS => {
                 // MDB_SET is the only cursor operation which doesn't
@@ -112,6 +119,7 @@ impl<'c, 'txn> Cursor<'c, 'txn> {
                 let res = unsafe {
                     ffi::mdb_cursor_get(self.handle, &mut self.key_val, &mut self.data_val, ffi::MDB_cursor_op::MDB_PREV_NODUP)
                 };
+                self.positioned = res == ffi::MDB_SUCCESS;
                 match res {
                     ffi::MDB_SUCCESS => {
                         self.valid_key = true;
@@ -122,14 +130,37 @@ impl<'c, 'txn> Cursor<'c, 'txn> {
                 }
             }
             if res == ffi::MDB_SUCCESS {
+                self.positioned = true;
                 self.valid_key = true;
                 self.valid_value = true;
                 return Ok(())
             }
         }
+        self.positioned = false;
         Err(MdbError::new_with_code(res))
     }
 
+    /// Binds cursor to another read only transaction, e.g. a reader
+    /// which was reset and renewed, reusing cursor handle instead of
+    /// allocating a new one. Cursor loses its position
+    pub fn renew(&mut self, txn: &'c dyn Txn<'txn>) -> MdbResult<()> {
+        if !txn.is_readonly() {
+            return Err(StateError("only cursors of read only transactions can be renewed".to_owned()));
+        }
+        try_mdb!(unsafe { ffi::mdb_cursor_renew(txn.get_handle(), self.handle) });
+        self.txn = txn;
+        self.valid_key = false;
+        self.valid_value = false;
+        self.positioned = false;
+        Ok(())
+    }
+
+    /// Returns true if last navigation succeeded, i.e. cursor
+    /// points to an entry. A fresh cursor isn't positioned
+    pub fn is_positioned(&self) -> bool {
+        self.positioned
+    }
+
     /// Moves cursor to first entry
     pub fn move_to_first(&mut self) -> MdbResult<()> {
         self.navigate(ffi::MDB_cursor_op::MDB_FIRST)
@@ -170,6 +201,20 @@ impl<'c, 'txn> Cursor<'c, 'txn> {
         }
     }
 
+    /// Moves cursor to the first item of key, i.e. the smallest one
+    /// in case of dup-keys
+    pub fn move_to_key_first_item<K: ToMdbValue>(&mut self, key: &K) -> MdbResult<()> {
+        self.move_to_key(key)?;
+        self.move_to_first_item()
+    }
+
+    /// Moves cursor to the last item of key, i.e. the largest one
+    /// in case of dup-keys
+    pub fn move_to_key_last_item<K: ToMdbValue>(&mut self, key: &K) -> MdbResult<()> {
+        self.move_to_key(key)?;
+        self.move_to_last_item()
+    }
+
     /// Moves cursor to first entry for key less than
     /// or equal to ke
     /// when the database supports dup-keys this will point the cursor to the first item of
@@ -232,6 +277,16 @@ impl<'c, 'txn> Cursor<'c, 'txn> {
         }
     }
 
+    /// Moves cursor to n-th key (counting from 0), skipping items
+    /// with duplicate keys. Returns `NotFound` if there are fewer keys
+    pub fn move_to_nth_key(&mut self, n: usize) -> MdbResult<()> {
+        self.move_to_first()?;
+        for _ in 0..n {
+            self.move_to_next_key()?;
+        }
+        Ok(())
+    }
+
     /// Moves cursor to next key, i.e. skip items
     /// with duplicate keys
     pub fn move_to_next_key(&mut self) -> MdbResult<()> {
@@ -309,6 +364,12 @@ impl<'c, 'txn> Cursor<'c, 'txn> {
         }
     }
 
+    /// Returns sizes of current key and value without converting them
+    pub fn current_sizes(&mut self) -> MdbResult<(usize, usize)> {
+        let (k, v) = self.get_plain()?;
+        Ok((k.get_size(), v.get_size()))
+    }
+
     /// Compares the cursor's current key with the specified other one.
     #[inline]
     fn cmp_key(&mut self, other: &MdbValue) -> MdbResult<Ordering> {
@@ -369,7 +430,19 @@ impl<'c, 'txn> Cursor<'c, 'txn> {
     fn set_value<V: ToMdbValue>(&mut self, value: &V, flags: c_uint) -> MdbResult<()> {
         self.ensure_key_valid()?;
         self.data_val = value.to_mdb_value().value;
-        lift_mdb!(unsafe {ffi::mdb_cursor_put(self.handle, &mut self.key_val, &mut self.data_val, flags)})
+        let len = self.key_val.mv_size + self.data_val.mv_size;
+        if let Some(native) = self.txn.get_native() {
+            native.check_write_limit(len)?;
+        }
+        let res = lift_mdb!(unsafe {ffi::mdb_cursor_put(self.handle, &mut self.key_val, &mut self.data_val, flags)});
+        if res.is_ok() {
+            // cursor is moved to written item
+            self.positioned = true;
+            if let Some(native) = self.txn.get_native() {
+                native.record_write(len);
+            }
+        }
+        res
     }
 
     pub fn set<K: ToMdbValue, V: ToMdbValue>(&mut self, key: &K, value: &V, flags: c_uint) -> MdbResult<()> {
@@ -395,8 +468,96 @@ impl<'c, 'txn> Cursor<'c, 'txn> {
         res
     }
 
+    /// Adds several items for current key at once, requires a
+    /// db created with DbDupFixed and values of the same size.
+    /// Returns number of written items
+    pub fn add_items<V: ToMdbValue>(&mut self, values: &[V]) -> MdbResult<usize> {
+        self.ensure_dup_fixed()?;
+        if values.is_empty() {
+            return Ok(0);
+        }
+
+        let item_size = values[0].to_mdb_value().get_size();
+        let mut packed: Vec<u8> = Vec::with_capacity(item_size * values.len());
+        for value in values.iter() {
+            let value = value.to_mdb_value();
+            if value.get_size() != item_size {
+                return Err(StateError("add_items requires values of the same size".to_owned()));
+            }
+            packed.extend_from_slice(unsafe {
+                std::slice::from_raw_parts(value.value.mv_data as *const u8, item_size)
+            });
+        }
+
+        self.ensure_key_valid()?;
+        // key is copied as it points to a page which could be touched by put
+        let key: Vec<u8> = unsafe {
+            std::slice::from_raw_parts(self.key_val.mv_data as *const u8, self.key_val.mv_size).to_vec()
+        };
+        let mut key_val = key.to_mdb_value().value;
+        let mut data = [
+            ffi::MDB_val { mv_size: item_size as size_t, mv_data: packed.as_ptr() as *const _ },
+            ffi::MDB_val { mv_size: values.len() as size_t, mv_data: ptr::null() },
+        ];
+
+        if let Some(native) = self.txn.get_native() {
+            native.check_write_limit(key.len() + packed.len())?;
+        }
+
+        self.valid_key = false;
+        self.valid_value = false;
+        try_mdb!(unsafe { ffi::mdb_cursor_put(self.handle, &mut key_val, data.as_mut_ptr(), ffi::MDB_MULTIPLE) });
+        if let Some(native) = self.txn.get_native() {
+            native.record_write(key.len() + data[1].mv_size * item_size);
+        }
+        Ok(data[1].mv_size)
+    }
+
+    /// Returns up to a page of items for current key at once.
+    /// Works only with DbDupFixed, otherwise fails with `MdbError::Incompatible`.
+    /// V has to be a plain integer or float of the same size as stored items
+    pub fn get_multiple<V: Plain>(&mut self) -> MdbResult<&[V]> {
+        self.get_multiple_with(ffi::MDB_cursor_op::MDB_GET_MULTIPLE)
+    }
+
+    /// Returns next page of items after [get_multiple](#method.get_multiple),
+    /// moving to next key once current one is exhausted.
+    /// Fails with `NotFound` when there are no more items
+    pub fn get_next_multiple<V: Plain>(&mut self) -> MdbResult<&[V]> {
+        self.get_multiple_with(ffi::MDB_cursor_op::MDB_NEXT_MULTIPLE)
+    }
+
+    fn get_multiple_with<V: Plain>(&mut self, op: ffi::MDB_cursor_op) -> MdbResult<&[V]> {
+        let mut data: ffi::MDB_val = unsafe { std::mem::zeroed() };
+        self.valid_key = false;
+        self.valid_value = false;
+        let res = unsafe { ffi::mdb_cursor_get(self.handle, &mut self.key_val, &mut data, op) };
+        self.positioned = res == ffi::MDB_SUCCESS;
+        try_mdb!(res);
+
+        let item_size = mem::size_of::<V>();
+        if item_size == 0 || !data.mv_size.is_multiple_of(item_size) || !(data.mv_data as usize).is_multiple_of(mem::align_of::<V>()) {
+            return Err(StateError("stored items don't match requested type".to_owned()));
+        }
+        Ok(unsafe { std::slice::from_raw_parts(data.mv_data as *const V, data.mv_size / item_size) })
+    }
+
+    fn ensure_dup_fixed(&self) -> MdbResult<()> {
+        if Database::new_with_handle(self.db).get_flags(self.txn)?.contains(DB_DUP_FIXED) {
+            Ok(())
+        } else {
+            Err(StateError("operation requires a DB_DUP_FIXED database".to_owned()))
+        }
+    }
+
     fn del_value(&mut self, flags: c_uint) -> MdbResult<()> {
-        lift_mdb!(unsafe { ffi::mdb_cursor_del(self.handle, flags) })
+        let res = lift_mdb!(unsafe { ffi::mdb_cursor_del(self.handle, flags) });
+        if res.is_ok() {
+            if let Some(native) = self.txn.get_native() {
+                native.record_write(0);
+            }
+        }
+        res
     }
 
     /// Deletes current key
@@ -426,6 +587,25 @@ impl<'c, 'txn> Cursor<'c, 'txn> {
         lift_mdb!(unsafe {ffi::mdb_cursor_count(self.handle, &mut tmp)}, tmp)
     }
 
+    /// Returns number of items for current key, leaving cursor
+    /// at the same item so iteration could be continued
+    pub fn dup_count(&mut self) -> MdbResult<usize> {
+        let count = self.item_count()?;
+        self.navigate(ffi::MDB_cursor_op::MDB_GET_CURRENT)?;
+        Ok(count)
+    }
+
+    /// Returns an iterator yielding owned key/value pairs starting
+    /// from current position and moving with `move_to_next`. Cursor
+    /// could be reused once the iterator is dropped
+    pub fn iter_from_current<'a>(&'a mut self) -> CursorStream<'a, 'c, 'txn> {
+        CursorStream {
+            cursor: self,
+            started: false,
+            done: false,
+        }
+    }
+
     pub fn get_item<'k, K: ToMdbValue>(self, k: &'k K) -> CursorItemAccessor<'c, 'k, 'txn, K> {
         CursorItemAccessor {
             cursor: self,
@@ -440,6 +620,77 @@ impl<'c, 'txn> Drop for Cursor<'c, 'txn> {
     }
 }
 
+/// Iterator through items from cursor's current position,
+/// see `Cursor::iter_from_current`. Iteration ends on the first
+/// error, e.g. if cursor isn't positioned
+#[derive(Debug)]
+pub struct CursorStream<'a, 'c: 'a, 'txn: 'c> {
+    cursor: &'a mut Cursor<'c, 'txn>,
+    started: bool,
+    done: bool,
+}
+
+impl<'a, 'c, 'txn> Iterator for CursorStream<'a, 'c, 'txn> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        if self.done {
+            return None;
+        }
+        // ~ MDB_NEXT on unpositioned cursor acts as MDB_FIRST, so
+        // stream has to stop once it failed
+        let moved = !self.started || self.cursor.move_to_next().is_ok();
+        self.started = true;
+        let item = if moved { self.cursor.get::<Vec<u8>, Vec<u8>>().ok() } else { None };
+        self.done = item.is_none();
+        item
+    }
+}
+
+/// Iterator removing entries as they are yielded, see
+/// `Database::drain`. Iteration ends on the first error
+#[derive(Debug)]
+pub struct DrainIter<'c, 'txn> {
+    cursor: Cursor<'c, 'txn>,
+    done: bool,
+}
+
+impl<'c, 'txn> DrainIter<'c, 'txn> {
+    pub fn new(cursor: Cursor<'c, 'txn>) -> DrainIter<'c, 'txn> {
+        DrainIter {
+            cursor,
+            done: false,
+        }
+    }
+}
+
+impl<'c, 'txn> Iterator for DrainIter<'c, 'txn> {
+    type Item = MdbResult<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<MdbResult<(Vec<u8>, Vec<u8>)>> {
+        if self.done {
+            return None;
+        }
+        // Position after delete differs for dup and plain databases
+        // and for the last item of a key, so cursor is always moved
+        // back to the first entry, which is the next one to drain
+        let res = self.cursor.move_to_first()
+            .and_then(|_| self.cursor.get::<Vec<u8>, Vec<u8>>())
+            .and_then(|entry| self.cursor.del_item().map(|_| entry));
+        match res {
+            Err(MdbError::NotFound) => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            },
+            Ok(entry) => Some(Ok(entry))
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct CursorItemAccessor<'c, 'k, 'txn, K: 'k> {
     cursor: Cursor<'c, 'txn>,
@@ -512,14 +763,74 @@ pub trait IterateCursor {
     }
 }
 
+/// Iteration which can also step backwards, makes `CursorIterator`
+/// a `DoubleEndedIterator`
+pub trait ReversibleCursor: IterateCursor {
+    /// Positions cursor at the last entry for backward iteration,
+    /// returns false if range is empty
+    fn init_cursor_back<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool;
+
+    /// Returns true if there is still data moving backwards
+    fn move_to_prev<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool;
+}
+
+/// Moves cursor to first item of current key, so moving
+/// backwards yields the same items as moving forward
+fn rewind_items(cursor: &mut Cursor) -> bool {
+    match cursor.item_count() {
+        Ok(cnt) if cnt > 1 => cursor.move_to_first_item().is_ok(),
+        _ => true,
+    }
+}
+
+fn is_gte(cursor: &mut Cursor, key: &MdbValue) -> bool {
+    match cursor.cmp_key(key) {
+        Ok(ord) => ord != Ordering::Less,
+        Err(_) => false,
+    }
+}
+
+/// Entry as raw key and value, used to find out where front and
+/// back of a `CursorIterator` meet
+type RawEntry = (ffi::MDB_val, ffi::MDB_val);
+
+/// Back end of `CursorIterator`, opened on first `next_back`
+#[derive(Debug)]
+struct CursorBack<'c, 'txn> {
+    cursor: Cursor<'c, 'txn>,
+    has_data: bool,
+    last: Option<RawEntry>,
+    dup_sort: bool,
+}
+
 #[derive(Debug)]
 pub struct CursorIterator<'c, 'txn, I> {
     inner: I,
     has_data: bool,
     cursor: Cursor<'c, 'txn>,
+    last: Option<RawEntry>,
+    back: Option<CursorBack<'c, 'txn>>,
     marker: ::std::marker::PhantomData<&'c ()>,
 }
 
+/// Compares entries in database order, values are compared only for
+/// DbAllowDups, as otherwise equal keys mean the same entry
+fn cmp_entries(cursor: &Cursor, a: &RawEntry, b: &RawEntry, dup_sort: bool) -> Ordering {
+    let (mut ak, mut av) = *a;
+    let (mut bk, mut bv) = *b;
+    let handle = cursor.txn.get_handle();
+    let res = unsafe { ffi::mdb_cmp(handle, cursor.db, &mut ak, &mut bk) };
+    let res = if res == 0 && dup_sort {
+        unsafe { ffi::mdb_dcmp(handle, cursor.db, &mut av, &mut bv) }
+    } else {
+        res
+    };
+    res.cmp(&0)
+}
+
+/// Iterator converting cursor values, see `CursorIterator::entries`
+pub type CursorMap<'c, 'txn, I, T> = Map<CursorIterator<'c, 'txn, I>, fn(CursorValue<'c>) -> T>;
+
 impl<'c, 'txn, I: IterateCursor + 'c> CursorIterator<'c, 'txn, I> {
     pub fn wrap(cursor: Cursor<'c, 'txn>, inner: I) -> CursorIterator<'c, 'txn, I> {
         let mut cursor = cursor;
@@ -528,6 +839,8 @@ impl<'c, 'txn, I: IterateCursor + 'c> CursorIterator<'c, 'txn, I> {
             inner,
             has_data,
             cursor,
+            last: None,
+            back: None,
             marker: ::std::marker::PhantomData,
         }
     }
@@ -536,34 +849,171 @@ impl<'c, 'txn, I: IterateCursor + 'c> CursorIterator<'c, 'txn, I> {
     fn unwrap(self) -> Cursor<'c, 'txn> {
         self.cursor
     }
-}
 
-impl<'c, 'txn, I: IterateCursor + 'c> Iterator for CursorIterator<'c, 'txn, I> {
-    type Item = CursorValue<'c>;
+    /// Returns an iterator which yields an error instead of silently
+    /// stopping if cursor fails to retrieve data, for example
+    /// because of a corrupted page. Iteration ends on the first error
+    pub fn results(self) -> CursorResults<'c, 'txn, I> {
+        CursorResults {
+            inner: self
+        }
+    }
 
-    fn next(&mut self) -> Option<CursorValue<'c>> {
+    /// Converts values lazily while iterating, i.e.
+    /// `db.iter(&txn)?.values::<String>()`
+    pub fn values<V: FromMdbValue + 'c>(self) -> CursorMap<'c, 'txn, I, V> {
+        self.map(|cv| FromMdbValue::from_mdb_value(&cv.value))
+    }
+
+    /// Converts keys lazily while iterating
+    pub fn keys<K: FromMdbValue + 'c>(self) -> CursorMap<'c, 'txn, I, K> {
+        self.map(|cv| FromMdbValue::from_mdb_value(&cv.key))
+    }
+
+    /// Converts key-value pairs lazily while iterating, i.e.
+    /// `db.iter(&txn)?.entries::<u32, String>()`
+    pub fn entries<K: FromMdbValue + 'c, V: FromMdbValue + 'c>(self) -> CursorMap<'c, 'txn, I, (K, V)> {
+        self.map(|cv| (FromMdbValue::from_mdb_value(&cv.key), FromMdbValue::from_mdb_value(&cv.value)))
+    }
+
+    /// Converts values lazily and keeps only those for which f
+    /// returns `Some`, without collecting them first
+    pub fn filter_map_values<V, T, F>(self, f: F) -> FilterMap<Self, impl FnMut(CursorValue<'c>) -> Option<T>>
+        where V: FromMdbValue + 'c, F: Fn(V) -> Option<T> {
+        self.filter_map(move |cv| f(FromMdbValue::from_mdb_value(&cv.value)))
+    }
+
+    /// Counts remaining entries by moving cursor only, without
+    /// retrieving values, which makes it cheaper than `Iterator::count`
+    /// for large ranges, e.g. `db.keyrange(&a, &b, &txn)?.count()`
+    pub fn count(mut self) -> usize {
+        if self.back.is_some() {
+            // has to stop where it meets back end
+            return Iterator::count(self);
+        }
+        let mut count = 0;
+        while self.has_data {
+            count += 1;
+            self.has_data = self.step_front();
+        }
+        count
+    }
+
+    /// Moves front cursor to the next entry of the range
+    fn step_front(&mut self) -> bool {
+        // SAFETY: `IterateCursor::move_to_next` asks for the cursor
+        // borrowed for 'c, but implementations only move it and don't
+        // keep the reference, so the borrow really ends with the call
+        let cursor = unsafe {
+            mem::transmute::<&mut Cursor<'c, 'txn>, &'c mut Cursor<'c, 'txn>>(&mut self.cursor)
+        };
+        self.inner.move_to_next(cursor)
+    }
+
+    fn next_result(&mut self) -> Option<MdbResult<CursorValue<'c>>> {
         if !self.has_data {
             None
         } else {
             match self.cursor.get_plain() {
-                Err(_) => None,
+                Err(MdbError::NotFound) => {
+                    self.has_data = false;
+                    None
+                },
+                Err(e) => {
+                    self.has_data = false;
+                    Some(Err(e))
+                },
                 Ok((k, v)) => {
-                    self.has_data = unsafe { self.inner.move_to_next(mem::transmute(&mut self.cursor)) };
-                    Some(CursorValue {
+                    let entry = (k.value, v.value);
+                    if let Some(CursorBack { last: Some(ref back_last), dup_sort, .. }) = self.back {
+                        if cmp_entries(&self.cursor, &entry, back_last, dup_sort) != Ordering::Less {
+                            self.has_data = false;
+                            return None;
+                        }
+                    }
+                    self.last = Some(entry);
+                    self.has_data = self.step_front();
+                    Some(Ok(CursorValue {
                         key: k,
                         value: v,
                         marker: ::std::marker::PhantomData
-                    })
+                    }))
                 }
             }
         }
     }
+}
+
+impl<'c, 'txn, I: IterateCursor + 'c> Iterator for CursorIterator<'c, 'txn, I> {
+    type Item = CursorValue<'c>;
+
+    fn next(&mut self) -> Option<CursorValue<'c>> {
+        self.next_result().and_then(|res| res.ok())
+    }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
         self.inner.get_size_hint(&self.cursor)
     }
 }
 
+/// Back end uses its own cursor, so `next` and `next_back` may be
+/// mixed. Iteration ends once they meet
+impl<'c, 'txn, I: ReversibleCursor + 'c> DoubleEndedIterator for CursorIterator<'c, 'txn, I> {
+    fn next_back(&mut self) -> Option<CursorValue<'c>> {
+        if self.back.is_none() {
+            let mut cursor = Cursor::new(self.cursor.txn, self.cursor.db).ok()?;
+            let has_data = self.inner.init_cursor_back(&mut cursor);
+            let dup_sort = Database::new_with_handle(self.cursor.db).get_flags(self.cursor.txn)
+                .map(|flags| flags.contains(DB_ALLOW_DUPS))
+                .unwrap_or(false);
+            self.back = Some(CursorBack { cursor, has_data, last: None, dup_sort });
+        }
+
+        let front_last = self.last;
+        let back = self.back.as_mut()?;
+        if !back.has_data {
+            return None;
+        }
+
+        match back.cursor.get_plain() {
+            Err(_) => {
+                back.has_data = false;
+                None
+            },
+            Ok((k, v)) => {
+                let entry = (k.value, v.value);
+                if let Some(ref front_last) = front_last {
+                    if cmp_entries(&back.cursor, &entry, front_last, back.dup_sort) != Ordering::Greater {
+                        back.has_data = false;
+                        return None;
+                    }
+                }
+                back.last = Some(entry);
+                back.has_data = self.inner.move_to_prev(&mut back.cursor);
+                Some(CursorValue {
+                    key: k,
+                    value: v,
+                    marker: ::std::marker::PhantomData
+                })
+            }
+        }
+    }
+}
+
+/// Fallible counterpart of `CursorIterator`, see `CursorIterator::results`
+#[derive(Debug)]
+pub struct CursorResults<'c, 'txn, I> {
+    inner: CursorIterator<'c, 'txn, I>,
+}
+
+impl<'c, 'txn, I: IterateCursor + 'c> Iterator for CursorResults<'c, 'txn, I> {
+    type Item = MdbResult<CursorValue<'c>>;
+
+    fn next(&mut self) -> Option<MdbResult<CursorValue<'c>>> {
+        self.inner.next_result()
+    }
+}
+
 #[derive(Debug)]
 pub struct CursorKeyRangeIter<'a> {
     start_key: MdbValue<'a>,
@@ -601,6 +1051,100 @@ impl<'iter> IterateCursor for CursorKeyRangeIter<'iter> {
     }
 }
 
+impl<'iter> ReversibleCursor for CursorKeyRangeIter<'iter> {
+    fn init_cursor_back<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        let ok = unsafe {
+            cursor.move_to_lte_key(mem::transmute::<&'a MdbValue<'a>, &'b MdbValue<'b>>(&self.end_key)).is_ok()
+        };
+        let ok = ok && (cursor.cmp_key(&self.end_key).is_less(self.end_inclusive) || cursor.move_to_prev_key().is_ok());
+        ok && is_gte(cursor, &self.start_key) && rewind_items(cursor)
+    }
+
+    fn move_to_prev<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        let moved = cursor.move_to_prev_key().is_ok();
+        moved && is_gte(cursor, &self.start_key) && rewind_items(cursor)
+    }
+}
+
+/// Iterates through keys within bounds, keys are copied so
+/// bounds don't have to outlive the iterator
+#[derive(Debug)]
+pub struct CursorRangeIter {
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
+
+fn bound_to_vec<K: ToMdbValue>(bound: Bound<&K>) -> Bound<Vec<u8>> {
+    let to_vec = |key: &K| {
+        let val = key.to_mdb_value();
+        unsafe { std::slice::from_raw_parts(val.value.mv_data as *const u8, val.get_size()).to_vec() }
+    };
+    match bound {
+        Bound::Included(key) => Bound::Included(to_vec(key)),
+        Bound::Excluded(key) => Bound::Excluded(to_vec(key)),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+impl CursorRangeIter {
+    pub fn new<K: ToMdbValue>(start: Bound<&K>, end: Bound<&K>) -> CursorRangeIter {
+        CursorRangeIter {
+            start: bound_to_vec(start),
+            end: bound_to_vec(end),
+        }
+    }
+
+    fn before_end(&self, cursor: &mut Cursor) -> bool {
+        match self.end {
+            Bound::Unbounded => true,
+            Bound::Included(ref key) => cursor.cmp_key(&key.to_mdb_value()).is_less(true),
+            Bound::Excluded(ref key) => cursor.cmp_key(&key.to_mdb_value()).is_less(false),
+        }
+    }
+
+    fn after_start(&self, cursor: &mut Cursor) -> bool {
+        match self.start {
+            Bound::Unbounded => true,
+            Bound::Included(ref key) => is_gte(cursor, &key.to_mdb_value()),
+            Bound::Excluded(ref key) => matches!(cursor.cmp_key(&key.to_mdb_value()), Ok(Ordering::Greater)),
+        }
+    }
+}
+
+impl IterateCursor for CursorRangeIter {
+    fn init_cursor<'a, 'b: 'a, 'txn>(&'a self, cursor: & mut Cursor<'b, 'txn>) -> bool {
+        let ok = match self.start {
+            Bound::Unbounded => cursor.move_to_first().is_ok(),
+            Bound::Included(ref key) => cursor.move_to_gte_key(key).is_ok(),
+            Bound::Excluded(ref key) => {
+                cursor.move_to_gte_key(key).is_ok() && (self.after_start(cursor) || cursor.move_to_next_key().is_ok())
+            }
+        };
+        ok && self.before_end(cursor)
+    }
+
+    fn move_to_next<'i, 'c: 'i, 'txn>(&'i self, cursor: &'c mut Cursor<'c, 'txn>) -> bool {
+        cursor.move_to_next_key().is_ok() && self.before_end(cursor)
+    }
+}
+
+impl ReversibleCursor for CursorRangeIter {
+    fn init_cursor_back<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        let ok = match self.end {
+            Bound::Unbounded => cursor.move_to_last().is_ok(),
+            Bound::Included(ref key) => cursor.move_to_lte_key(key).is_ok(),
+            Bound::Excluded(ref key) => {
+                cursor.move_to_lte_key(key).is_ok() && (self.before_end(cursor) || cursor.move_to_prev_key().is_ok())
+            }
+        };
+        ok && self.after_start(cursor) && rewind_items(cursor)
+    }
+
+    fn move_to_prev<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        cursor.move_to_prev_key().is_ok() && self.after_start(cursor) && rewind_items(cursor)
+    }
+}
+
 #[derive(Debug)]
 pub struct CursorFromKeyIter<'a> {
     start_key: MdbValue<'a>,
@@ -628,6 +1172,18 @@ impl<'iter> IterateCursor for CursorFromKeyIter<'iter> {
     }
 }
 
+impl<'iter> ReversibleCursor for CursorFromKeyIter<'iter> {
+    fn init_cursor_back<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        let ok = cursor.move_to_last().is_ok();
+        ok && is_gte(cursor, &self.start_key) && rewind_items(cursor)
+    }
+
+    fn move_to_prev<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        let moved = cursor.move_to_prev_key().is_ok();
+        moved && is_gte(cursor, &self.start_key) && rewind_items(cursor)
+    }
+}
+
 
 #[derive(Debug)]
 pub struct CursorToKeyIter<'a> {
@@ -660,6 +1216,127 @@ impl<'iter> IterateCursor for CursorToKeyIter<'iter> {
     }
 }
 
+impl<'iter> ReversibleCursor for CursorToKeyIter<'iter> {
+    fn init_cursor_back<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        let ok = unsafe {
+            cursor.move_to_lte_key(mem::transmute::<&'a MdbValue<'a>, &'b MdbValue<'b>>(&self.end_key)).is_ok()
+        };
+        let ok = ok && (cursor.cmp_key(&self.end_key).is_less(false) || cursor.move_to_prev_key().is_ok());
+        ok && rewind_items(cursor)
+    }
+
+    fn move_to_prev<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        cursor.move_to_prev_key().is_ok() && rewind_items(cursor)
+    }
+}
+
+#[derive(Debug)]
+pub struct CursorSuffixIter<'a> {
+    suffix: &'a [u8],
+}
+
+impl<'a> CursorSuffixIter<'a> {
+    pub fn new(suffix: &'a [u8]) -> CursorSuffixIter<'a> {
+        CursorSuffixIter {
+            suffix
+        }
+    }
+
+    fn has_suffix(&self, cursor: &mut Cursor) -> bool {
+        match cursor.get_key::<&[u8]>() {
+            Ok(key) => key.ends_with(self.suffix),
+            Err(_) => false
+        }
+    }
+}
+
+impl<'iter> IterateCursor for CursorSuffixIter<'iter> {
+    fn init_cursor<'a, 'b: 'a, 'txn>(&'a self, cursor: & mut Cursor<'b, 'txn>) -> bool {
+        // ~ with reversed keys suffix is a lower bound of matching keys,
+        // LMDB rejects empty keys, while every key ends with empty suffix
+        let ok = if self.suffix.is_empty() {
+            cursor.move_to_first().is_ok()
+        } else {
+            cursor.move_to_gte_key(&self.suffix).is_ok()
+        };
+        ok && self.has_suffix(cursor)
+    }
+
+    fn move_to_next<'i, 'c: 'i, 'txn>(&'i self, cursor: &'c mut Cursor<'c, 'txn>) -> bool {
+        let moved = cursor.move_to_next_key().is_ok();
+        moved && self.has_suffix(cursor)
+    }
+}
+
+#[derive(Debug)]
+pub struct CursorPrefixIter<'a> {
+    prefix: &'a [u8],
+}
+
+impl<'a> CursorPrefixIter<'a> {
+    pub fn new(prefix: &'a [u8]) -> CursorPrefixIter<'a> {
+        CursorPrefixIter {
+            prefix
+        }
+    }
+
+    fn has_prefix(&self, cursor: &mut Cursor) -> bool {
+        match cursor.get_key::<&[u8]>() {
+            Ok(key) => key.starts_with(self.prefix),
+            Err(_) => false
+        }
+    }
+}
+
+impl<'iter> IterateCursor for CursorPrefixIter<'iter> {
+    fn init_cursor<'a, 'b: 'a, 'txn>(&'a self, cursor: & mut Cursor<'b, 'txn>) -> bool {
+        // LMDB rejects empty keys, while every key starts with empty prefix
+        let ok = if self.prefix.is_empty() {
+            cursor.move_to_first().is_ok()
+        } else {
+            cursor.move_to_gte_key(&self.prefix).is_ok()
+        };
+        ok && self.has_prefix(cursor)
+    }
+
+    fn move_to_next<'i, 'c: 'i, 'txn>(&'i self, cursor: &'c mut Cursor<'c, 'txn>) -> bool {
+        let moved = cursor.move_to_next_key().is_ok();
+        moved && self.has_prefix(cursor)
+    }
+}
+
+impl<'iter> ReversibleCursor for CursorPrefixIter<'iter> {
+    fn init_cursor_back<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        // last matching key is the one before the first key past prefix
+        let ok = match prefix_upper_bound(self.prefix) {
+            Some(ref upper) => match cursor.move_to_gte_key(upper) {
+                Ok(_) => cursor.move_to_prev_key().is_ok(),
+                Err(MdbError::NotFound) => cursor.move_to_last().is_ok(),
+                Err(_) => false,
+            },
+            None => cursor.move_to_last().is_ok(),
+        };
+        ok && self.has_prefix(cursor) && rewind_items(cursor)
+    }
+
+    fn move_to_prev<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        cursor.move_to_prev_key().is_ok() && self.has_prefix(cursor) && rewind_items(cursor)
+    }
+}
+
+/// Smallest key greater than all keys starting with prefix, `None`
+/// if there is no such key, i.e. prefix is empty or all `0xff`
+fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut upper = prefix.to_vec();
+    while let Some(last) = upper.pop() {
+        if last < 0xff {
+            upper.push(last + 1);
+            return Some(upper);
+        }
+    }
+    None
+}
+
 #[allow(missing_copy_implementations)]
 #[derive(Debug)]
 pub struct CursorIter;
@@ -674,9 +1351,20 @@ impl<'iter> IterateCursor for CursorIter {
     }
 }
 
+impl ReversibleCursor for CursorIter {
+    fn init_cursor_back<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        cursor.move_to_last().is_ok() && rewind_items(cursor)
+    }
+
+    fn move_to_prev<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        cursor.move_to_prev_key().is_ok() && rewind_items(cursor)
+    }
+}
+
 #[derive(Debug)]
 pub struct CursorItemIter<'a> {
     key: MdbValue<'a>,
+    remaining: Cell<usize>,
     marker: ::std::marker::PhantomData<&'a ()>,
 }
 
@@ -684,26 +1372,58 @@ impl<'a> CursorItemIter<'a> {
     pub fn new<K: ToMdbValue+'a>(key: &'a K) -> CursorItemIter<'a> {
         CursorItemIter {
             key: key.to_mdb_value(),
+            remaining: Cell::new(0),
             marker: ::std::marker::PhantomData
         }
     }
+
+    /// Counts down a yielded item, returns false if it was the last
+    fn take_one(&self) -> bool {
+        self.remaining.set(self.remaining.get().saturating_sub(1));
+        self.remaining.get() > 0
+    }
 }
 
 impl<'iter> IterateCursor for CursorItemIter<'iter> {
     fn init_cursor<'a, 'b: 'a, 'txn>(&'a self, cursor: & mut Cursor<'b, 'txn>) -> bool {
-        unsafe {
+        let found = unsafe {
             cursor.move_to_key(mem::transmute::<&MdbValue, &'b MdbValue<'b>>(&self.key)).is_ok()
-        }
+        };
+        // count is taken once, so that deleting items while iterating
+        // doesn't shift it. Databases without duplicates hold one item
+        let count = if found { cursor.item_count().unwrap_or(1) } else { 0 };
+        self.remaining.set(count);
+        found
     }
 
     fn move_to_next<'i, 'c: 'i, 'txn>(&'i self, cursor: &'c mut Cursor<'c, 'txn>) -> bool {
-        cursor.move_to_next_item().is_ok()
+        // without duplicates LMDB would step to the next key
+        self.take_one() && cursor.move_to_next_item().is_ok()
     }
 
-    fn get_size_hint(&self, c: &Cursor) -> (usize, Option<usize>) {
-        match c.item_count() {
-            Err(_) => (0, None),
-            Ok(cnt) => (0, Some(cnt as usize))
+    fn get_size_hint(&self, _c: &Cursor) -> (usize, Option<usize>) {
+        (self.remaining.get(), Some(self.remaining.get()))
+    }
+}
+
+impl<'iter> ReversibleCursor for CursorItemIter<'iter> {
+    fn init_cursor_back<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        // count is shared with front end, so it isn't taken again
+        let found = unsafe {
+            cursor.move_to_key(mem::transmute::<&MdbValue, &'b MdbValue<'b>>(&self.key)).is_ok()
+        };
+        found && match cursor.item_count() {
+            Ok(cnt) if cnt > 1 => cursor.move_to_last_item().is_ok(),
+            _ => true,
         }
     }
-}
\ No newline at end of file
+
+    fn move_to_prev<'a, 'b: 'a, 'txn>(&'a self, cursor: &mut Cursor<'b, 'txn>) -> bool {
+        self.take_one() && cursor.move_to_prev_item().is_ok()
+    }
+}
+
+/// Number of items is known up front, both ends count down the
+/// same number. It stays exact unless other items are added for the
+/// key or an error stops iteration early
+impl<'c, 'txn> ExactSizeIterator for CursorIterator<'c, 'txn, CursorItemIter<'c>> {}
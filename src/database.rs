@@ -1,11 +1,30 @@
-use libc::{c_int, c_uint, };
+use libc::{c_int, c_uint, size_t};
 use ffi::{self, MDB_val};
-use crate::traits::{ToMdbValue, FromMdbValue};
+use crate::traits::{ToMdbValue, FromMdbValue, TryFromMdbValue};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
 use std::ptr;
+use std::slice;
 
 use crate::core::{ MdbError, MdbResult, MdbValue, StateError };
 use crate::transaction::{ TransactionState, Txn };
-use crate::cursor::{ Cursor, CursorFromKeyIter, CursorItemIter, CursorIter, CursorIterator, CursorKeyRangeIter, CursorToKeyIter };
+use crate::environment::Environment;
+use crate::compare::IntWidth;
+use crate::utils::{from_hex, to_hex};
+use crate::cursor::{ Cursor, CursorValue, DrainIter, CursorFromKeyIter, CursorItemIter, CursorIter, CursorIterator, CursorKeyRangeIter, CursorPrefixIter, CursorRangeIter, CursorSuffixIter, CursorToKeyIter };
+
+fn read_version(value: &[u8], offset: usize) -> MdbResult<u64> {
+    match value.get(offset..offset + 8) {
+        Some(bytes) => {
+            let mut buf = [0u8; 8];
+            buf.copy_from_slice(bytes);
+            Ok(u64::from_be_bytes(buf))
+        },
+        None => Err(MdbError::Conversion(format!("value of {} bytes has no version at offset {}", value.len(), offset))),
+    }
+}
 
 bitflags! {
     #[doc = "A set of database flags"]
@@ -45,6 +64,9 @@ bitflags! {
     }
 }
 
+/// LMDB always assigns this handle to the default (unnamed) database
+const MAIN_DBI: ffi::MDB_dbi = 1;
+
 /// Database
 #[derive(Debug, Clone)]
 pub struct Database {
@@ -59,6 +81,25 @@ impl Database {
         Database { handle }
     }
 
+    /// Returns true if it is the default (unnamed) database
+    pub fn is_default(&self) -> bool {
+        self.handle == MAIN_DBI
+    }
+
+    /// Binds database to key type K and value type V
+    pub fn typed<K: ToMdbValue, V: ToMdbValue>(self) -> TypedDatabase<K, V> {
+        TypedDatabase {
+            db: self,
+            _types: PhantomData,
+        }
+    }
+
+    /// Returns flags database was created with
+    pub fn get_flags<'txn>(&self, txn: &'_ dyn Txn<'txn>) -> MdbResult<DbFlags> {
+        let mut flags: c_uint = 0;
+        lift_mdb!(unsafe { ffi::mdb_dbi_flags(txn.get_handle(), self.handle, &mut flags) }, DbFlags::from_bits_truncate(flags))
+    }
+
     /// Retrieves current db's statistics.
     pub fn stat<'txn>(&self, txn: &'_ dyn Txn<'txn>) -> MdbResult<ffi::MDB_stat> {
         let mut tmp: ffi::MDB_stat = unsafe { std::mem::zeroed() };
@@ -82,6 +123,63 @@ impl Database {
         self.get_value(key, txn)
     }
 
+    /// Retrieves a value by key, validating stored data before conversion.
+    /// Returns `Conversion` error if it doesn't represent a valid `V`
+    pub fn try_get<'txn, V: TryFromMdbValue + 'txn>(&self, key: &dyn ToMdbValue, txn: &'_ dyn Txn<'txn>) -> MdbResult<V> {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        let mut key_val = key.to_mdb_value();
+        unsafe {
+            let mut data_val: MdbValue = std::mem::zeroed();
+            try_mdb!(ffi::mdb_get(txn.get_handle(), self.handle, &mut key_val.value, &mut data_val.value));
+            TryFromMdbValue::try_from_mdb_value(&data_val)
+        }
+    }
+
+    /// Retrieves a value by key as a slice pointing directly into the
+    /// memory map, without copying.
+    ///
+    /// **Warning**: the slice is only valid while transaction is
+    /// alive. It must not be used after commit or abort, nor after
+    /// the value is overwritten or deleted in a write transaction,
+    /// as its pages may be reused
+    pub fn get_bytes<'a, 'txn>(&self, key: &dyn ToMdbValue, txn: &'a dyn Txn<'txn>) -> MdbResult<&'a [u8]> {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        let mut key_val = key.to_mdb_value();
+        unsafe {
+            let mut data_val: MdbValue = std::mem::zeroed();
+            try_mdb!(ffi::mdb_get(txn.get_handle(), self.handle, &mut key_val.value, &mut data_val.value));
+            Ok(slice::from_raw_parts(data_val.value.mv_data as *const u8, data_val.get_size()))
+        }
+    }
+
+    /// Checks presence of each key with a single cursor, result is
+    /// positional. Sorted keys are cheaper to look up, as cursor
+    /// stays on pages close to the previous key
+    pub fn contains_keys<'txn, K: ToMdbValue>(&self, keys: &[K], txn: &'_ dyn Txn<'txn>) -> MdbResult<Vec<bool>> {
+        let mut cursor = self.new_cursor(txn)?;
+        let mut found = Vec::with_capacity(keys.len());
+        for key in keys {
+            match cursor.move_to_key(key) {
+                Ok(_) => found.push(true),
+                Err(MdbError::NotFound) => found.push(false),
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(found)
+    }
+
+    /// Returns size of value stored by key without converting it.
+    /// In case of DbAllowDups it is size of the first value
+    pub fn value_len<'txn>(&self, key: &dyn ToMdbValue, txn: &'_ dyn Txn<'txn>) -> MdbResult<usize> {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        let mut key_val = key.to_mdb_value();
+        unsafe {
+            let mut data_val: MdbValue = std::mem::zeroed();
+            try_mdb!(ffi::mdb_get(txn.get_handle(), self.handle, &mut key_val.value, &mut data_val.value));
+            Ok(data_val.get_size())
+        }
+    }
+
     fn set_value<'txn>(&self, key: &dyn ToMdbValue, value: &dyn ToMdbValue, txn: &'_ dyn Txn<'txn>) -> MdbResult<()> {
         self.set_value_with_flags(key, value, 0, txn)
     }
@@ -91,11 +189,40 @@ impl Database {
         unsafe {
             let mut key_val = key.to_mdb_value();
             let mut data_val = value.to_mdb_value();
+            let len = key_val.get_size() + data_val.get_size();
+            if let Some(native) = txn.get_native() {
+                native.check_write_limit(len)?;
+            }
 
-            lift_mdb!(ffi::mdb_put(txn.get_handle(), self.handle, &mut key_val.value, &mut data_val.value, flags))
+            let res = lift_mdb!(ffi::mdb_put(txn.get_handle(), self.handle, &mut key_val.value, &mut data_val.value, flags));
+            if res.is_ok() {
+                if let Some(native) = txn.get_native() {
+                    native.record_write(len);
+                }
+            }
+            res
         }
     }
 
+    /// Sets value for key and returns previous value, `None` if key
+    /// didn't exist, like `HashMap::insert`. Fails with `StateError`
+    /// for DbAllowDups as it isn't clear which item to replace
+    pub fn replace_value<'txn, V: FromMdbValue + 'static>(&self, key: &dyn ToMdbValue, value: &dyn ToMdbValue, txn: &'_ dyn Txn<'txn>) -> MdbResult<Option<V>> {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        if self.get_flags(txn)?.contains(DB_ALLOW_DUPS) {
+            return Err(StateError("replace_value is not supported with DB_ALLOW_DUPS".to_owned()));
+        }
+
+        // old value has to be owned, put may overwrite its page
+        let old = match self.get_value::<V>(key, txn) {
+            Ok(old) => Some(old),
+            Err(MdbError::NotFound) => None,
+            Err(e) => return Err(e),
+        };
+        self.set_value(key, value, txn)?;
+        Ok(old)
+    }
+
     /// Sets value for key. In case of DbAllowDups it will add a new item
     pub fn set<'txn>(&self, key: &dyn ToMdbValue, value: &dyn ToMdbValue, txn: &'_ dyn Txn<'txn>) -> MdbResult<()> {
 
@@ -104,13 +231,71 @@ impl Database {
         self.set_value(key, value, txn)
     }
 
+    /// Reserves uninitialized space of len bytes for key's value and
+    /// returns it to be filled in place. Slice is valid until next
+    /// write or end of transaction. Not supported with DbAllowDups
+    ///
+    /// ```compile_fail
+    /// # use lmdb_rs_et::{DbFlags, EnvBuilder};
+    /// let env = EnvBuilder::new().open("reserve-db", 0o777).unwrap();
+    /// let db = env.get_default_db(DbFlags::empty()).unwrap();
+    /// let txn = env.new_transaction().unwrap();
+    /// let buf = db.reserve(&"key", 4, &txn).unwrap();
+    /// txn.commit().unwrap();
+    /// buf.copy_from_slice(b"late");
+    /// ```
+    // slice points into a dirty page owned by LMDB, borrowing txn only
+    // keeps it from outliving transaction
+    #[allow(clippy::mut_from_ref)]
+    pub fn reserve<'a, 'txn>(&self, key: &dyn ToMdbValue, len: usize, txn: &'a dyn Txn<'txn>) -> MdbResult<&'a mut [u8]> {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        if self.get_flags(txn)?.contains(DB_ALLOW_DUPS) {
+            return Err(StateError("reserve is not supported with DB_ALLOW_DUPS".to_owned()));
+        }
+
+        unsafe {
+            let mut key_val = key.to_mdb_value();
+            let mut data_val = MDB_val {
+                mv_size: len as size_t,
+                mv_data: ptr::null(),
+            };
+            let total = key_val.get_size() + len;
+            if let Some(native) = txn.get_native() {
+                native.check_write_limit(total)?;
+            }
+
+            try_mdb!(ffi::mdb_put(txn.get_handle(), self.handle, &mut key_val.value, &mut data_val, ffi::MDB_RESERVE));
+            if let Some(native) = txn.get_native() {
+                native.record_write(total);
+            }
+            Ok(slice::from_raw_parts_mut(data_val.mv_data as *mut u8, data_val.mv_size as usize))
+        }
+    }
+
     /// Appends new key-value pair to database, starting a new page instead of splitting an
     /// existing one if necessary. Requires that key be >= all existing keys in the database
-    /// (or will return KeyExists error).
+    /// (or will return `AppendOutOfOrder` error with both keys in hex).
     pub fn append<'txn, K: ToMdbValue, V: ToMdbValue>(&self, key: &K, value: &V, txn: &'_ dyn Txn<'txn>) -> MdbResult<()> {
 
         assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
-        self.set_value_with_flags(key, value, ffi::MDB_APPEND, txn)
+        match self.set_value_with_flags(key, value, ffi::MDB_APPEND, txn) {
+            Err(MdbError::KeyExists) => Err(self.append_order_error(key, txn)),
+            res => res,
+        }
+    }
+
+    fn append_order_error<'txn>(&self, key: &dyn ToMdbValue, txn: &'_ dyn Txn<'txn>) -> MdbError {
+        let last_key = self.new_cursor(txn).and_then(|mut cursor| {
+            cursor.move_to_last()?;
+            cursor.get_key::<Vec<u8>>()
+        });
+        let key = key.to_mdb_value();
+        let key = unsafe { slice::from_raw_parts(key.value.mv_data as *const u8, key.get_size()) };
+        let msg = match last_key {
+            Ok(last_key) => format!("append out of order: key 0x{} is not greater than last key 0x{}", to_hex(key), to_hex(&last_key)),
+            Err(_) => format!("append out of order: key 0x{}", to_hex(key)),
+        };
+        MdbError::AppendOutOfOrder(msg)
     }
 
     /// Appends new value for the given key (requires DbAllowDups), starting a new page instead
@@ -129,11 +314,76 @@ impl Database {
         self.set_value_with_flags(key, value, ffi::MDB_NOOVERWRITE, txn)
     }
 
+    /// Adds delta to a counter stored as little-endian i64 and
+    /// returns new value, absent counter starts from 0. Fails
+    /// on overflow leaving stored value untouched
+    pub fn incr<'txn>(&self, key: &dyn ToMdbValue, delta: i64, txn: &'_ dyn Txn<'txn>) -> MdbResult<i64> {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        let current = match self.get_value::<&[u8]>(key, txn) {
+            Ok(bytes) if bytes.len() == 8 => {
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(bytes);
+                i64::from_le_bytes(buf)
+            },
+            Ok(bytes) => return Err(MdbError::Conversion(format!("counter must be 8 bytes, found {}", bytes.len()))),
+            Err(MdbError::NotFound) => 0,
+            Err(e) => return Err(e),
+        };
+
+        let new_value = current.checked_add(delta)
+            .ok_or_else(|| StateError(format!("counter overflow: {} + {}", current, delta)))?;
+        self.set_value(key, &&new_value.to_le_bytes()[..], txn)?;
+        Ok(new_value)
+    }
+
+    /// Stores value only if its version is greater than version of
+    /// stored one (or if key doesn't exist) and returns whether it was
+    /// written. Version is a big-endian `u64` at version_offset of both
+    /// values, allowing last-write-wins replication
+    pub fn put_if_newer<'txn, K: ToMdbValue>(&self, key: &K, value: &[u8], version_offset: usize, txn: &'_ dyn Txn<'txn>) -> MdbResult<bool> {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        let new_version = read_version(value, version_offset)?;
+        match self.get_value::<&[u8]>(key, txn) {
+            Ok(current) if read_version(current, version_offset)? >= new_version => return Ok(false),
+            Ok(_) | Err(MdbError::NotFound) => (),
+            Err(e) => return Err(e),
+        }
+        self.set_value(key, &value, txn)?;
+        Ok(true)
+    }
+
+    /// Reads value for key (`None` if key doesn't exist), passes it
+    /// to f and stores result back. Key is deleted if f returns `None`.
+    /// In case of DbAllowDups f gets the first item and result is
+    /// added as a new item
+    pub fn update<'txn, V, F>(&self, key: &dyn ToMdbValue, txn: &'_ dyn Txn<'txn>, f: F) -> MdbResult<()>
+        where V: FromMdbValue + ToMdbValue + 'txn, F: FnOnce(Option<V>) -> Option<V> {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        let current = match self.get_value::<V>(key, txn) {
+            Ok(value) => Some(value),
+            Err(MdbError::NotFound) => None,
+            Err(e) => return Err(e),
+        };
+        let existed = current.is_some();
+
+        match f(current) {
+            Some(value) => self.set_value(key, &value, txn),
+            None if existed => self.del_value(key, txn),
+            None => Ok(())
+        }
+    }
+
     fn del_value<'txn>(&self, key: &dyn ToMdbValue, txn: &'_ dyn Txn<'txn>) -> MdbResult<()> {
 
         unsafe {
             let mut key_val = key.to_mdb_value();
-            lift_mdb!(ffi::mdb_del(txn.get_handle(), self.handle, &mut key_val.value, ptr::null_mut()))
+            let res = lift_mdb!(ffi::mdb_del(txn.get_handle(), self.handle, &mut key_val.value, ptr::null_mut()));
+            if res.is_ok() {
+                if let Some(native) = txn.get_native() {
+                    native.record_write(0);
+                }
+            }
+            res
         }
     }
 
@@ -144,6 +394,16 @@ impl Database {
         self.del_value(key, txn)
     }
 
+    /// Deletes key if it exists. Returns true if it was deleted and
+    /// false if there was no such key
+    pub fn remove<'txn>(&self, key: &dyn ToMdbValue, txn: &'_ dyn Txn<'txn>) -> MdbResult<bool> {
+        match self.del(key, txn) {
+            Ok(_) => Ok(true),
+            Err(MdbError::NotFound) => Ok(false),
+            Err(e) => Err(e)
+        }
+    }
+
     /// Should be used only with DbAllowDups. Deletes corresponding (key, value)
     pub fn del_item<'txn>(&self, key: &dyn ToMdbValue, data: &dyn ToMdbValue, txn: &'_ dyn Txn<'txn>) -> MdbResult<()> {
 
@@ -152,7 +412,132 @@ impl Database {
             let mut key_val = key.to_mdb_value();
             let mut data_val = data.to_mdb_value();
 
-            lift_mdb!(ffi::mdb_del(txn.get_handle(), self.handle, &mut key_val.value, &mut data_val.value))
+            let res = lift_mdb!(ffi::mdb_del(txn.get_handle(), self.handle, &mut key_val.value, &mut data_val.value));
+            if res.is_ok() {
+                if let Some(native) = txn.get_native() {
+                    native.record_write(0);
+                }
+            }
+            res
+        }
+    }
+
+    /// Reads value for key and deletes it in one cursor pass, returns
+    /// `None` if key doesn't exist. In case of DbAllowDups only the
+    /// first item is taken and deleted. Requires a read-write transaction
+    pub fn take<'txn, K: ToMdbValue, V: FromMdbValue>(&self, key: &K, txn: &'_ dyn Txn<'txn>) -> MdbResult<Option<V>> {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        let mut cursor = self.new_cursor(txn)?;
+        match cursor.move_to_key(key) {
+            Ok(_) => (),
+            Err(MdbError::NotFound) => return Ok(None),
+            Err(e) => return Err(e)
+        }
+        let value = cursor.get_value::<V>()?;
+        cursor.del_item()?;
+        Ok(Some(value))
+    }
+
+    /// Gets value for key and deletes it, like [take](#method.take)
+    /// but fails with `NotFound` if key doesn't exist. In case of
+    /// DbAllowDups only the first item is removed, other items of
+    /// the key are kept
+    pub fn pop<'txn, V: FromMdbValue>(&self, key: &dyn ToMdbValue, txn: &'_ dyn Txn<'txn>) -> MdbResult<V> {
+        self.take(&key.to_mdb_value(), txn)?.ok_or(MdbError::NotFound)
+    }
+
+    /// Reads value for key and passes it to f, `None` if there is no
+    /// such key. Used with a write transaction, isolation guarantees
+    /// value isn't changed by others until transaction ends, so f
+    /// could decide what to write next in the same transaction
+    pub fn with_value<'txn, K, V, F, T>(&self, key: &K, txn: &'_ dyn Txn<'txn>, f: F) -> MdbResult<T>
+        where K: ToMdbValue, V: FromMdbValue + 'txn, F: FnOnce(Option<V>) -> MdbResult<T> {
+        let value = match self.get::<V>(key, txn) {
+            Ok(value) => Some(value),
+            Err(MdbError::NotFound) => None,
+            Err(e) => return Err(e)
+        };
+        f(value)
+    }
+
+    /// Returns iterator which removes every entry (every item in
+    /// case of DbAllowDups) as it is yielded, so that work committed
+    /// in transaction doesn't return on restart. Requires a read-write
+    /// transaction
+    pub fn drain<'c, 'txn>(&'c self, txn: &'c dyn Txn<'txn>) -> MdbResult<DrainIter<'c, 'txn>> {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        if txn.is_readonly() {
+            return Err(StateError("drain requires a read-write transaction".to_owned()));
+        }
+        self.new_cursor(txn).map(DrainIter::new)
+    }
+
+    /// Retrieves n-th key (counting from 0) and its value, `None` if
+    /// there are fewer keys. In case of DbAllowDups it is the first item
+    pub fn nth<'txn, K: FromMdbValue, V: FromMdbValue>(&self, n: usize, txn: &'_ dyn Txn<'txn>) -> MdbResult<Option<(K, V)>> {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        let mut cursor = self.new_cursor(txn)?;
+        match cursor.move_to_nth_key(n) {
+            Ok(_) => cursor.get::<K, V>().map(Some),
+            Err(MdbError::NotFound) => Ok(None),
+            Err(e) => Err(e)
+        }
+    }
+
+    fn pop_entry<'txn, K, V>(&self, txn: &'_ dyn Txn<'txn>, last: bool) -> MdbResult<Option<(K, V)>>
+        where K: FromMdbValue, V: FromMdbValue {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        let mut cursor = self.new_cursor(txn)?;
+        let moved = if last { cursor.move_to_last() } else { cursor.move_to_first() };
+        match moved {
+            Ok(_) => (),
+            Err(MdbError::NotFound) => return Ok(None),
+            Err(e) => return Err(e)
+        }
+        let entry = cursor.get::<K, V>()?;
+        cursor.del_item()?;
+        Ok(Some(entry))
+    }
+
+    /// Removes first entry and returns it, `None` if db is empty.
+    /// In case of DbAllowDups only first item is removed. Requires
+    /// a read-write transaction
+    pub fn pop_first<'txn, K: FromMdbValue, V: FromMdbValue>(&self, txn: &'_ dyn Txn<'txn>) -> MdbResult<Option<(K, V)>> {
+        self.pop_entry(txn, false)
+    }
+
+    /// Removes last entry and returns it, `None` if db is empty.
+    /// In case of DbAllowDups only last item is removed. Requires
+    /// a read-write transaction
+    pub fn pop_last<'txn, K: FromMdbValue, V: FromMdbValue>(&self, txn: &'_ dyn Txn<'txn>) -> MdbResult<Option<(K, V)>> {
+        self.pop_entry(txn, true)
+    }
+
+    /// Deletes every item for which `pred(key, value)` returns false,
+    /// returns number of deleted items. Requires a read-write transaction
+    pub fn retain<'txn, F>(&self, txn: &'_ dyn Txn<'txn>, pred: F) -> MdbResult<usize>
+        where F: Fn(&[u8], &[u8]) -> bool {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        let mut cursor = self.new_cursor(txn)?;
+        let mut deleted = 0;
+        let mut res = cursor.move_to_first();
+        loop {
+            match res {
+                Ok(_) => (),
+                Err(MdbError::NotFound) => return Ok(deleted),
+                Err(e) => return Err(e)
+            }
+            let keep = {
+                let (k, v) = cursor.get::<&[u8], &[u8]>()?;
+                pred(k, v)
+            };
+            if !keep {
+                // ~ LMDB keeps cursor at deleted position, so the
+                // following MDB_NEXT lands on the next item
+                cursor.del_item()?;
+                deleted += 1;
+            }
+            res = cursor.move_to_next();
         }
     }
 
@@ -162,16 +547,41 @@ impl Database {
         Cursor::new(txn, self.handle)
     }
 
-    /// Deletes current db, also moves it out
+    /// Deletes current db, also moves it out. The default database
+    /// can't be deleted, use `clear` instead
     pub fn del_db<'txn>(self, txn: &'_ dyn Txn<'txn>) -> MdbResult<()> {
 
         assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        if self.is_default() {
+            return Err(StateError("cannot drop the default database; use clear() instead".to_owned()));
+        }
         unsafe {
             txn.get_env().drop_db_from_cache(self.handle);
             lift_mdb!(ffi::mdb_drop(txn.get_handle(), self.handle, 1))
         }
     }
 
+    /// Drops db and creates it again with flags in the same
+    /// transaction, e.g. to change flags in a schema migration. Unlike
+    /// `clear` it also forgets comparators set on the old handle. The
+    /// default database can't be recreated
+    pub fn recreate<'txn>(self, flags: DbFlags, txn: &'_ dyn Txn<'txn>) -> MdbResult<Database> {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        if self.is_default() {
+            return Err(StateError("cannot recreate the default database; use clear() instead".to_owned()));
+        }
+        txn.get_env().recreate_db(self.handle, flags, txn).map(Database::new_with_handle)
+    }
+
+    /// LMDB syncs only whole environment, so this is the same as
+    /// `Environment::sync(true)` and flushes committed changes of all
+    /// databases. It is useful as a barrier in environments opened with
+    /// `ENV_CREATE_NO_SYNC`. Changes of txn itself aren't flushed until
+    /// it is committed
+    pub fn flush<'txn>(&self, txn: &'_ dyn Txn<'txn>) -> MdbResult<()> {
+        txn.get_env().sync(true)
+    }
+
     /// Removes all key/values from db
     pub fn clear<'txn>(&self, txn: &'_ dyn Txn<'txn>) -> MdbResult<()> {
 
@@ -181,12 +591,206 @@ impl Database {
         }
     }
 
+    /// Returns number of distinct keys. For DbAllowDups it requires
+    /// a pass through all keys, otherwise it's taken from db stats
+    pub fn distinct_key_count<'txn>(&self, txn: &'_ dyn Txn<'txn>) -> MdbResult<usize> {
+        if !self.get_flags(txn)?.contains(DB_ALLOW_DUPS) {
+            return Ok(self.stat(txn)?.ms_entries);
+        }
+
+        self.iter(txn)?.results().try_fold(0, |count, cv| cv.map(|_| count + 1))
+    }
+
+    /// Adds all items of other into this database, skipping items
+    /// which are already there. Returns number of added items. Both
+    /// databases must be opened with DbAllowDups
+    pub fn union_into<'txn>(&self, other: &Database, txn: &'_ dyn Txn<'txn>) -> MdbResult<usize> {
+        assert_state_eq!(txn, txn.get_state(), TransactionState::Normal);
+        if !self.get_flags(txn)?.contains(DB_ALLOW_DUPS) || !other.get_flags(txn)?.contains(DB_ALLOW_DUPS) {
+            return Err(StateError("union_into requires DB_ALLOW_DUPS on both databases".to_owned()));
+        }
+
+        let mut added = 0;
+        let mut cursor = other.new_cursor(txn)?;
+        let mut res = cursor.move_to_first();
+        loop {
+            match res {
+                Ok(_) => (),
+                Err(MdbError::NotFound) => return Ok(added),
+                Err(e) => return Err(e)
+            }
+            let (key, value) = cursor.get::<Vec<u8>, Vec<u8>>()?;
+            match self.set_value_with_flags(&key, &value, ffi::MDB_NODUPDATA, txn) {
+                Ok(_) => added += 1,
+                Err(MdbError::KeyExists) => (),
+                Err(e) => return Err(e)
+            }
+            res = cursor.move_to_next();
+        }
+    }
+
+    /// Copies all entries (all items in case of DbAllowDups) into
+    /// database dest_name of another environment, which is created
+    /// with the same flags. Returns number of copied items
+    pub fn copy_to_env<'txn>(&self, dest: &Environment, dest_name: &str, src_txn: &'_ dyn Txn<'txn>) -> MdbResult<usize> {
+        assert_state_eq!(txn, src_txn.get_state(), TransactionState::Normal);
+        let dest_db = dest.create_db(dest_name, self.get_flags(src_txn)?)?;
+
+        dest.transact(|dest_txn| {
+            let mut copied = 0;
+            let mut cursor = self.new_cursor(src_txn)?;
+            let mut res = cursor.move_to_first();
+            loop {
+                match res {
+                    Ok(_) => (),
+                    Err(MdbError::NotFound) => return Ok(copied),
+                    Err(e) => return Err(e)
+                }
+                let (key, value) = cursor.get::<&[u8], &[u8]>()?;
+                dest_db.set(&key, &value, dest_txn)?;
+                copied += 1;
+                res = cursor.move_to_next();
+            }
+        })
+    }
+
+    /// Returns sizes of all items of key in their sort order, without
+    /// copying them. Empty if key doesn't exist
+    pub fn item_sizes<'txn, K: ToMdbValue>(&self, key: &K, txn: &'_ dyn Txn<'txn>) -> MdbResult<Vec<usize>> {
+        let mut cursor = self.new_cursor(txn)?;
+        match cursor.move_to_key(key) {
+            Ok(_) => (),
+            Err(MdbError::NotFound) => return Ok(Vec::new()),
+            Err(e) => return Err(e)
+        }
+
+        let mut sizes = Vec::new();
+        loop {
+            sizes.push(cursor.get_value::<&[u8]>()?.len());
+            match cursor.move_to_next_item() {
+                Ok(_) => (),
+                Err(MdbError::NotFound) => return Ok(sizes),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    /// Reads whole database into a map from key to all its items in
+    /// order they are stored. Everything is copied into memory at
+    /// once, so it is meant for small databases, e.g. configuration
+    pub fn to_grouped_map<'txn, K, V>(&self, txn: &'_ dyn Txn<'txn>) -> MdbResult<BTreeMap<K, Vec<V>>>
+        where K: FromMdbValue + Ord + 'txn, V: FromMdbValue + 'txn {
+        let mut map: BTreeMap<K, Vec<V>> = BTreeMap::new();
+        let mut cursor = self.new_cursor(txn)?;
+        let mut res = cursor.move_to_first();
+        loop {
+            match res {
+                Ok(_) => (),
+                Err(MdbError::NotFound) => return Ok(map),
+                Err(e) => return Err(e)
+            }
+            let (key, value) = cursor.get::<K, V>()?;
+            map.entry(key).or_default().push(value);
+            res = cursor.move_to_next();
+        }
+    }
+
+    /// Reads all distinct keys in sorted order
+    pub fn keys<'c, 'txn, K: FromMdbValue + 'c>(&self, txn: &'c dyn Txn<'txn>) -> MdbResult<Vec<K>> {
+        let mut keys = Vec::with_capacity(self.distinct_key_count(txn)?);
+        for cv in self.iter(txn)?.results() {
+            keys.push(cv?.get_key());
+        }
+        Ok(keys)
+    }
+
+    /// Writes all entries in a readable form, one `key => value` per
+    /// line. Printable ASCII is written quoted, anything else as hex.
+    /// Duplicate values follow their key on separate lines
+    pub fn dump<'txn, W: Write>(&self, txn: &'_ dyn Txn<'txn>, w: &mut W) -> MdbResult<()> {
+        let mut cursor = self.new_cursor(txn)?;
+        let mut res = cursor.move_to_first();
+        let mut last_key: Option<Vec<u8>> = None;
+
+        while res.is_ok() {
+            let (key, value) = cursor.get::<Vec<u8>, &[u8]>()?;
+            let key_repr = dump_repr(&key);
+            let line = if last_key.as_ref() == Some(&key) {
+                format!("{} => {}", " ".repeat(key_repr.len()), dump_repr(value))
+            } else {
+                format!("{} => {}", key_repr, dump_repr(value))
+            };
+            writeln!(w, "{}", line)
+                .map_err(|e| StateError(format!("failed to write dump: {}", e)))?;
+
+            last_key = Some(key);
+            res = cursor.move_to_next();
+        }
+
+        match res {
+            Err(MdbError::NotFound) => Ok(()),
+            other => other,
+        }
+    }
+
+    /// Loads entries from text produced either by `dump` or by
+    /// `mdb_dump` tool, returns number of inserted entries
+    pub fn load<'txn, R: BufRead>(&self, txn: &'_ dyn Txn<'txn>, r: &mut R) -> MdbResult<usize> {
+        let mut lines = Vec::new();
+        for line in r.lines() {
+            lines.push(line.map_err(|e| StateError(format!("failed to read dump: {}", e)))?);
+        }
+        let invalid = |n: usize| StateError(format!("invalid dump line {}: {}", n + 1, lines[n]));
+
+        let mut count = 0;
+        if lines.first().is_some_and(|l| l.starts_with("VERSION=")) {
+            // ~ mdb_dump: header up to HEADER=END, then key and value lines
+            let header_end = lines.iter().position(|l| l == "HEADER=END").ok_or_else(|| invalid(0))?;
+            let printable = lines[..header_end].iter().any(|l| l == "format=print");
+            let mut n = header_end + 1;
+            while n < lines.len() && lines[n] != "DATA=END" {
+                let key = parse_mdb_dump_line(&lines[n], printable).ok_or_else(|| invalid(n))?;
+                let value = lines.get(n + 1)
+                    .and_then(|l| parse_mdb_dump_line(l, printable))
+                    .ok_or_else(|| invalid(n + 1))?;
+                self.set(&key, &value, txn)?;
+                count += 1;
+                n += 2;
+            }
+        } else {
+            let mut last_key: Option<Vec<u8>> = None;
+            for (n, line) in lines.iter().enumerate() {
+                let (key, value) = parse_dump_line(line).ok_or_else(|| invalid(n))?;
+                if key.is_some() {
+                    last_key = key;
+                }
+                let key = last_key.as_ref().ok_or_else(|| invalid(n))?;
+                self.set(key, &value, txn)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
     /// Returns an iterator for all values in database
     pub fn iter<'c, 'txn>(&self, txn: &'c dyn Txn<'txn>) -> MdbResult<CursorIterator<'c, 'txn, CursorIter>> {
         self.new_cursor(txn)
             .and_then(|c| Ok(CursorIterator::wrap(c, CursorIter)))
     }
 
+    /// Returns a value to be used directly in `for` loop, e.g.
+    /// `for cv in db.scan(&txn) { .. }`. Cursor is opened when loop
+    /// starts, which panics if it fails. Use [try_scan](#method.try_scan)
+    /// to handle the error instead
+    pub fn scan<'c, 'txn>(&'c self, txn: &'c dyn Txn<'txn>) -> Scan<'c, 'txn> {
+        Scan { db: self, txn }
+    }
+
+    /// Fallible counterpart of [scan](#method.scan), same as [iter](#method.iter)
+    pub fn try_scan<'c, 'txn>(&'c self, txn: &'c dyn Txn<'txn>) -> MdbResult<CursorIterator<'c, 'txn, CursorIter>> {
+        self.iter(txn)
+    }
+
     /// Returns an iterator through keys starting with start_key (>=), start_key is included
     pub fn keyrange_from<'c, 'txn, K: ToMdbValue + 'c>(&'c self, start_key: &'c K, txn: &'c dyn Txn<'txn>) -> MdbResult<CursorIterator<'c, 'txn, CursorFromKeyIter>> {
         let cursor = self.new_cursor(txn)?;
@@ -227,6 +831,48 @@ impl Database {
         Ok(wrap)
     }
 
+    /// Returns an iterator through keys within a standard range, e.g.
+    /// `db.range(10u64..20, &txn)` or `db.range(..=5u64, &txn)`.
+    ///
+    /// `a..b` and `..b` exclude b, `a..=b` and `..=b` include it,
+    /// `a..` and `..` run to the last key. Like `keyrange`, it visits
+    /// only the first item of each key in DbAllowDups databases
+    pub fn range<'c, 'txn, K: ToMdbValue, R: RangeBounds<K>>(&'c self, bounds: R, txn: &'c dyn Txn<'txn>)
+                               -> MdbResult<CursorIterator<'c, 'txn, CursorRangeIter>>
+    {
+        let cursor = self.new_cursor(txn)?;
+        let key_range = CursorRangeIter::new(bounds.start_bound(), bounds.end_bound());
+        Ok(CursorIterator::wrap(cursor, key_range))
+    }
+
+    /// Returns an iterator through keys starting with prefix. Keys are
+    /// expected to be sorted lexicographically, i.e. database isn't
+    /// created with DbReverseKey, DbIntKey or a custom comparator
+    pub fn prefix_iter<'c, 'txn>(&'c self, prefix: &'c [u8], txn: &'c dyn Txn<'txn>) -> MdbResult<CursorIterator<'c, 'txn, CursorPrefixIter<'c>>> {
+        let cursor = self.new_cursor(txn)?;
+        Ok(CursorIterator::wrap(cursor, CursorPrefixIter::new(prefix)))
+    }
+
+    /// Returns an iterator through keys ending with suffix. Requires
+    /// a database created with DbReverseKey, as only then such keys
+    /// are sorted next to each other. It iterates forward only
+    ///
+    /// ```compile_fail
+    /// # use lmdb_rs_et::{DbFlags, EnvBuilder};
+    /// # use lmdb_rs_et::database::DB_REVERSE_KEY;
+    /// let env = EnvBuilder::new().open("suffix-db", 0o777).unwrap();
+    /// let db = env.get_default_db(DB_REVERSE_KEY).unwrap();
+    /// let txn = env.get_reader().unwrap();
+    /// let last = db.suffix_iter(b".txt", &txn).unwrap().next_back();
+    /// ```
+    pub fn suffix_iter<'c, 'txn>(&'c self, suffix: &'c [u8], txn: &'c dyn Txn<'txn>) -> MdbResult<CursorIterator<'c, 'txn, CursorSuffixIter<'c>>> {
+        if !self.get_flags(txn)?.contains(DB_REVERSE_KEY) {
+            return Err(StateError("suffix_iter requires a DB_REVERSE_KEY database".to_owned()));
+        }
+        let cursor = self.new_cursor(txn)?;
+        Ok(CursorIterator::wrap(cursor, CursorSuffixIter::new(suffix)))
+    }
+
     /// Returns an iterator for all items (i.e. values with same key)
     pub fn item_iter<'c, 'txn, 'db: 'c, K: ToMdbValue>(&'db self, key: &'c K, txn: &'c dyn Txn<'txn>) -> MdbResult<CursorIterator<'c, 'txn, CursorItemIter<'c>>> {
         let cursor = self.new_cursor(txn)?;
@@ -234,6 +880,71 @@ impl Database {
         Ok(CursorIterator::<'c, 'txn>::wrap(cursor, inner_iter))
     }
 
+    /// Checks whether both databases contain the same key/value pairs,
+    /// comparing raw bytes. For databases with duplicates the full
+    /// sorted set of items of every key is compared.
+    pub fn equals<'txn, 'otxn>(&self, other: &Database, self_txn: &'_ dyn Txn<'txn>, other_txn: &'_ dyn Txn<'otxn>) -> MdbResult<bool> {
+        let mut cursor = self.new_cursor(self_txn)?;
+        let mut other_cursor = other.new_cursor(other_txn)?;
+
+        let mut res = cursor.move_to_first();
+        let mut other_res = other_cursor.move_to_first();
+        loop {
+            match (res, other_res) {
+                (Err(MdbError::NotFound), Err(MdbError::NotFound)) => return Ok(true),
+                (Err(MdbError::NotFound), Ok(_)) | (Ok(_), Err(MdbError::NotFound)) => return Ok(false),
+                (Err(e), _) | (_, Err(e)) => return Err(e),
+                (Ok(_), Ok(_)) => ()
+            }
+
+            if cursor.get::<&[u8], &[u8]>()? != other_cursor.get::<&[u8], &[u8]>()? {
+                return Ok(false);
+            }
+
+            // ~ MDB_NEXT walks through all items of a key before moving to the next one
+            res = cursor.move_to_next();
+            other_res = other_cursor.move_to_next();
+        }
+    }
+
+    /// Returns up to `limit` entries with keys greater than `after` (or from the
+    /// beginning if it is `None`) using a fresh short-lived reader, so long scans
+    /// don't pin pages. Also returns the key to resume from, `None` if there is
+    /// no more data. Like `iter` it yields only the first item of each key.
+    /// Fails with `StateError` if `limit` is 0.
+    pub fn scan_resume(&self, env: &Environment, after: Option<&[u8]>, limit: usize) -> MdbResult<ScanPage> {
+        if limit == 0 {
+            return Err(StateError("scan_resume requires a non-zero limit".to_owned()));
+        }
+        let txn = env.get_reader()?;
+        let mut cursor = self.new_cursor(&txn)?;
+        let mut entries = Vec::with_capacity(limit);
+
+        let mut res = match after {
+            None => cursor.move_to_first(),
+            Some(after) => match cursor.move_to_gte_key(&after) {
+                Ok(_) if cursor.get_key::<&[u8]>()? == after => cursor.move_to_next_key(),
+                res => res
+            }
+        };
+
+        loop {
+            match res {
+                Ok(_) => (),
+                Err(MdbError::NotFound) => return Ok((entries, None)),
+                Err(e) => return Err(e)
+            }
+            if entries.len() == limit {
+                break;
+            }
+            entries.push(cursor.get::<Vec<u8>, Vec<u8>>()?);
+            res = cursor.move_to_next_key();
+        }
+
+        let resume_key = entries.last().map(|(k, _)| k.clone());
+        Ok((entries, resume_key))
+    }
+
     /// Sets the key compare function for this database.
     ///
     /// Warning: This function must be called before any data access functions
@@ -252,6 +963,13 @@ impl Database {
         })
     }
 
+    /// Sets one of comparators from `compare` module for integer keys
+    /// of given layout, see [set_compare](#method.set_compare). Same
+    /// as there, it must be called before any data access
+    pub fn set_int_compare<'txn>(&self, width: IntWidth, txn: &'_ dyn Txn<'txn>) -> MdbResult<()> {
+        self.set_compare(width.comparator(), txn)
+    }
+
     /// Sets the value comparison function for values of the same key in this database.
     ///
     /// Warning: This function must be called before any data access functions
@@ -273,6 +991,182 @@ impl Database {
     }
 }
 
+/// Values serialized with bincode. Keys are stored raw to keep
+/// their sort order. Changing serialization format of a value type
+/// makes values stored earlier unreadable
+#[cfg(feature = "serde")]
+impl Database {
+    pub fn set_serde<'txn, K: ToMdbValue, V: serde::Serialize>(&self, key: &K, value: &V, txn: &'_ dyn Txn<'txn>) -> MdbResult<()> {
+        let bytes = bincode::serialize(value)
+            .map_err(|e| MdbError::Conversion(format!("failed to serialize value: {}", e)))?;
+        self.set(key, &bytes, txn)
+    }
+
+    pub fn get_serde<'txn, K: ToMdbValue, V: serde::de::DeserializeOwned>(&self, key: &K, txn: &'_ dyn Txn<'txn>) -> MdbResult<V> {
+        let bytes = self.get::<&[u8]>(key, txn)?;
+        bincode::deserialize(bytes)
+            .map_err(|e| MdbError::Conversion(format!("failed to deserialize value: {}", e)))
+    }
+}
+
+fn dump_repr(bytes: &[u8]) -> String {
+    // quotes inside would make it ambiguous for load
+    if bytes.iter().all(|&b| b == b' ' || (b.is_ascii_graphic() && b != b'"')) {
+        format!("\"{}\"", String::from_utf8_lossy(bytes))
+    } else {
+        format!("0x{}", to_hex(bytes))
+    }
+}
+
+/// Splits a line written by `dump` into key (`None` for
+/// continuation lines) and value
+fn parse_dump_line(line: &str) -> Option<(Option<Vec<u8>>, Vec<u8>)> {
+    let (key, rest) = if line.starts_with(' ') {
+        (None, line.trim_start())
+    } else if let Some(quoted) = line.strip_prefix('"') {
+        let end = quoted.find('"')?;
+        (Some(quoted.as_bytes()[..end].to_vec()), &quoted[end + 1..])
+    } else {
+        let end = line.find(' ')?;
+        (Some(parse_dump_repr(&line[..end])?), &line[end..])
+    };
+
+    let rest = rest.trim_start().strip_prefix("=> ")?;
+    Some((key, parse_dump_repr(rest)?))
+}
+
+fn parse_dump_repr(repr: &str) -> Option<Vec<u8>> {
+    if let Some(hex) = repr.strip_prefix("0x") {
+        from_hex(hex)
+    } else if repr.len() >= 2 && repr.starts_with('"') && repr.ends_with('"') {
+        Some(repr.as_bytes()[1..repr.len() - 1].to_vec())
+    } else {
+        None
+    }
+}
+
+/// Decodes data line of `mdb_dump`, either plain hex or
+/// printable with backslash escapes when dumped with `-p`
+fn parse_mdb_dump_line(line: &str, printable: bool) -> Option<Vec<u8>> {
+    let line = line.strip_prefix(' ')?;
+    if !printable {
+        return from_hex(line);
+    }
+
+    let bytes = line.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            out.push(bytes[i]);
+            i += 1;
+        } else if bytes.get(i + 1) == Some(&b'\\') {
+            out.push(b'\\');
+            i += 2;
+        } else {
+            out.extend(from_hex(line.get(i + 1..i + 3)?)?);
+            i += 3;
+        }
+    }
+    Some(out)
+}
+
+/// Database bound to fixed key and value types, obtained through
+/// [typed](struct.Database.html#method.typed)
+#[derive(Debug)]
+pub struct TypedDatabase<K, V> {
+    db: Database,
+    _types: PhantomData<(K, V)>,
+}
+
+impl<K, V> Clone for TypedDatabase<K, V> {
+    fn clone(&self) -> TypedDatabase<K, V> {
+        TypedDatabase {
+            db: self.db.clone(),
+            _types: PhantomData,
+        }
+    }
+}
+
+impl<K: ToMdbValue, V: ToMdbValue> TypedDatabase<K, V> {
+    pub fn get<'txn>(&self, key: &K, txn: &'_ dyn Txn<'txn>) -> MdbResult<V> where V: FromMdbValue + 'txn {
+        self.db.get(key, txn)
+    }
+
+    pub fn set<'txn>(&self, key: &K, value: &V, txn: &'_ dyn Txn<'txn>) -> MdbResult<()> {
+        self.db.set(key, value, txn)
+    }
+
+    pub fn del<'txn>(&self, key: &K, txn: &'_ dyn Txn<'txn>) -> MdbResult<()> {
+        self.db.del(key, txn)
+    }
+
+    /// Returns underlying untyped database
+    pub fn untyped(&self) -> &Database {
+        &self.db
+    }
+}
+
+/// Iteration through all entries of database, obtained through
+/// [scan](struct.Database.html#method.scan)
+#[derive(Debug)]
+pub struct Scan<'c, 'txn> {
+    db: &'c Database,
+    txn: &'c dyn Txn<'txn>,
+}
+
+impl<'c, 'txn> IntoIterator for Scan<'c, 'txn> {
+    type Item = CursorValue<'c>;
+    type IntoIter = CursorIterator<'c, 'txn, CursorIter>;
+
+    /// Panics if cursor can't be opened, e.g. transaction is no longer valid
+    fn into_iter(self) -> CursorIterator<'c, 'txn, CursorIter> {
+        match self.db.iter(self.txn) {
+            Ok(iter) => iter,
+            Err(e) => panic!("failed to open cursor for scan: {}", e),
+        }
+    }
+}
+
+/// Entries returned by
+/// [scan_resume](struct.Database.html#method.scan_resume) and the key
+/// to resume from
+pub type ScanPage = (Vec<(Vec<u8>, Vec<u8>)>, Option<Vec<u8>>);
+
+/// Keeps position of a scan performed in pages through
+/// [scan_resume](struct.Database.html#method.scan_resume)
+#[derive(Clone, Debug, Default)]
+pub struct ResumableScan {
+    last_key: Option<Vec<u8>>,
+    finished: bool,
+}
+
+impl ResumableScan {
+    pub fn new() -> ResumableScan {
+        ResumableScan::default()
+    }
+
+    /// Returns next page of at most `limit` entries, empty once scan is finished
+    pub fn next_page(&mut self, db: &Database, env: &Environment, limit: usize) -> MdbResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        if self.finished {
+            return Ok(Vec::new());
+        }
+        let (entries, resume_key) = db.scan_resume(env, self.last_key.as_ref().map(|k| &k[..]), limit)?;
+        self.finished = resume_key.is_none();
+        self.last_key = resume_key;
+        Ok(entries)
+    }
+
+    /// Last key returned so far, `None` if nothing was returned yet or scan is finished
+    pub fn last_key(&self) -> Option<&[u8]> {
+        self.last_key.as_ref().map(|k| &k[..])
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Copy, Clone, Debug)]
 /// A handle to a database
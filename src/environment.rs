@@ -1,19 +1,22 @@
-use libc::{c_int, c_uint, size_t};
+use libc::{c_char, c_int, c_uint, c_void, size_t};
 use std;
 use std::borrow::ToOwned;
 use std::cell::{UnsafeCell};
 use std::collections::HashMap;
-use std::ffi::{CString};
-use std::path::Path;
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
 use std::ptr;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use ffi::{self};
 
-use crate::core::{ MdbError, MdbResult };
+use crate::core::{ EnvInfo, MdbError, MdbResult, Stat };
 use crate::database::Database;
-use crate::transaction::{ NativeTransaction, Transaction, ReadonlyTransaction };
+use crate::transaction::{ NativeTransaction, Transaction, ReadonlyTransaction, Txn };
 use crate::database::{ DbFlags, DB_CREATE };
+use crate::traits::{ FromMdbValue, ToMdbValue };
+use crate::writer::{ self, WriteSender, WriterHandle };
 
 bitflags! {
     #[doc = "A set of environment flags which could be changed after opening"]
@@ -196,7 +199,9 @@ pub struct EnvBuilder {
     max_readers: Option<usize>,
     max_dbs: Option<usize>,
     map_size: Option<u64>,
+    max_map_size: Option<u64>,
     autocreate_dir: bool,
+    require_existing_dir: bool,
 }
 
 impl EnvBuilder {
@@ -206,7 +211,9 @@ impl EnvBuilder {
             max_readers: None,
             max_dbs: None,
             map_size: None,
+            max_map_size: None,
             autocreate_dir: true,
+            require_existing_dir: false,
         }
     }
 
@@ -229,9 +236,26 @@ impl EnvBuilder {
     }
 
     /// Sets max environment size, i.e. size in memory/disk of
-    /// all data
+    /// all data. Size is rounded up to a multiple of OS page size, or
+    /// down to the largest one if it doesn't fit into u64
     pub fn map_size(mut self, map_size: u64) -> EnvBuilder {
-        self.map_size = Some(map_size);
+        let page_size = os_page_size();
+        let rounded = map_size.div_ceil(page_size).checked_mul(page_size);
+        self.map_size = Some(rounded.unwrap_or(u64::MAX / page_size * page_size));
+        self
+    }
+
+    /// Map size environment will be opened with, i.e. page rounded
+    /// value set by `map_size()` or LMDB default
+    pub fn effective_map_size(&self) -> u64 {
+        self.map_size.unwrap_or(DEFAULT_MAP_SIZE)
+    }
+
+    /// Sets a hard ceiling for map size, growing map beyond it
+    /// with [set_mapsize](struct.Environment.html#method.set_mapsize)
+    /// fails with `MapFull` error
+    pub fn max_map_size(mut self, max_map_size: u64) -> EnvBuilder {
+        self.max_map_size = Some(max_map_size);
         self
     }
 
@@ -242,10 +266,21 @@ impl EnvBuilder {
         self
     }
 
+    /// Makes opening fail with `InvalidPath` if dir doesn't exist,
+    /// whatever `autocreate_dir` is set to. Missing dir usually means
+    /// misconfiguration, which shouldn't be hidden by creating an empty
+    /// environment (default is false)
+    pub fn require_existing_dir(mut self, require_existing_dir: bool) -> EnvBuilder {
+        self.require_existing_dir = require_existing_dir;
+        self
+    }
+
     /// Opens environment in specified path
     pub fn open<P: AsRef<Path>>(self, path: P, perms: u32) -> MdbResult<Environment> {
         let changeable_flags: EnvCreateFlags = ENV_CREATE_MAP_ASYNC | ENV_CREATE_NO_MEM_INIT | ENV_CREATE_NO_SYNC | ENV_CREATE_NO_META_SYNC;
 
+        check_map_size(self.effective_map_size(), self.max_map_size)?;
+
         let env: *mut ffi::MDB_env = ptr::null_mut();
         unsafe {
             let p_env: *mut *mut ffi::MDB_env = &env as *const *mut ffi::MDB_env as *mut *mut ffi::MDB_env;
@@ -267,11 +302,16 @@ impl EnvBuilder {
             try_mdb!(unsafe { ffi::mdb_env_set_maxdbs(env, max_dbs as u32)});
         }
 
-        if self.autocreate_dir {
-            EnvBuilder::check_path(&path, self.flags)?;
+        if self.require_existing_dir {
+            EnvBuilder::check_path(&path, self.flags, false)?;
+        } else if self.autocreate_dir {
+            EnvBuilder::check_path(&path, self.flags, true)?;
         }
 
         let is_readonly = self.flags.contains(ENV_CREATE_READONLY);
+        let is_write_map = self.flags.contains(ENV_CREATE_WRITE_MAP);
+        let max_map_size = self.max_map_size;
+        let max_dbs = self.max_dbs;
 
         let res = unsafe {
             // FIXME: revert back once `convert` is stable
@@ -286,7 +326,13 @@ impl EnvBuilder {
         drop(self);
         match res {
             ffi::MDB_SUCCESS => {
-                Ok(Environment::from_raw(env, is_readonly))
+                let env = Environment::from_raw(env, is_readonly, is_write_map, max_map_size, max_dbs);
+                if let Ok(page_size) = env.page_size() {
+                    if page_size as u64 != os_page_size() {
+                        warn!("environment page size {} differs from OS page size {}", page_size, os_page_size());
+                    }
+                }
+                Ok(env)
             },
             _ => {
                 unsafe { ffi::mdb_env_close(env); }
@@ -296,7 +342,7 @@ impl EnvBuilder {
 
     }
 
-    fn check_path<P: AsRef<Path>>(path: P, flags: EnvCreateFlags) -> MdbResult<()> {
+    fn check_path<P: AsRef<Path>>(path: P, flags: EnvCreateFlags, create: bool) -> MdbResult<()> {
         use std::{fs, io};
 
         if flags.contains(ENV_CREATE_NO_SUB_DIR) {
@@ -315,7 +361,7 @@ impl EnvBuilder {
                 }
             },
             Err(e) => {
-                if e.kind() == io::ErrorKind::NotFound {
+                if e.kind() == io::ErrorKind::NotFound && create {
                     fs::create_dir_all(path.as_ref()).map_err(|e| {
                         error!("failed to auto create dir: {}", e);
                         MdbError::InvalidPath
@@ -328,13 +374,116 @@ impl EnvBuilder {
     }
 }
 
+/// Returns version of linked LMDB library as `(major, minor, patch, version string)`
+pub fn version() -> (c_int, c_int, c_int, String) {
+    let mut major: c_int = 0;
+    let mut minor: c_int = 0;
+    let mut patch: c_int = 0;
+    let version = unsafe {
+        // returned string is static, so it is safe to copy it
+        let s = ffi::mdb_version(&mut major, &mut minor, &mut patch);
+        CStr::from_ptr(s).to_string_lossy().into_owned()
+    };
+    (major, minor, patch, version)
+}
+
+/// Returns max key size linked LMDB library was built with
+/// (`MDB_MAXKEYSIZE`, 511 by default). Unlike
+/// [max_key_size](struct.Environment.html#method.max_key_size) it
+/// doesn't need an open environment
+pub fn compiled_max_key_size() -> MdbResult<usize> {
+    let mut env: *mut ffi::MDB_env = ptr::null_mut();
+    try_mdb!(unsafe { ffi::mdb_env_create(&mut env) });
+    // size is a build time constant, so environment isn't opened
+    let size = unsafe { ffi::mdb_env_get_maxkeysize(env) };
+    unsafe { ffi::mdb_env_close(env); }
+    if size > 0 {
+        Ok(size as usize)
+    } else {
+        Err(MdbError::StateError(format!("invalid max key size {}", size)))
+    }
+}
+
+/// Opens environment in path read-only, reads value stored by key in
+/// named database and closes everything. Returns `None` if there is
+/// no such key. Meant for scripts, as opening environment is costly.
+///
+/// **Warning**: it must not be called while environment in path is
+/// open in this process. File locks are per process, so closing the
+/// second environment silently drops locks of the one already open
+pub fn read_one<P: AsRef<Path>, V: FromMdbValue + 'static>(path: P, db_name: &str, key: &dyn ToMdbValue) -> MdbResult<Option<V>> {
+    let env = EnvBuilder::new()
+        .flags(ENV_CREATE_READONLY)
+        .max_dbs(1)
+        .autocreate_dir(false)
+        .open(path, 0o600)?;
+    let db = env.open_existing_db(db_name, DbFlags::empty())?;
+    let reader = env.get_reader()?;
+    match db.get::<V>(key, &reader) {
+        Ok(value) => Ok(Some(value)),
+        Err(MdbError::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Information about a single slot of the reader lock table
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ReaderInfo {
+    /// Process id of the reader
+    pub pid: i32,
+    /// Thread id of the reader
+    pub thread_id: usize,
+    /// Snapshot held by the reader, `None` if the slot is reset
+    pub txnid: Option<u64>,
+}
+
+impl ReaderInfo {
+    /// Parses a line produced by `mdb_reader_list`, i.e. `pid thread txnid`
+    /// where thread is in hex and txnid is `-` for a reset slot
+    fn parse(line: &str) -> Option<ReaderInfo> {
+        let mut parts = line.split_whitespace();
+        let pid = parts.next()?.parse().ok()?;
+        let thread_id = usize::from_str_radix(parts.next()?, 16).ok()?;
+        let txnid = match parts.next()? {
+            "-" => None,
+            t => Some(t.parse().ok()?),
+        };
+        Some(ReaderInfo { pid, thread_id, txnid })
+    }
+}
+
+extern "C" fn collect_reader_line(msg: *const c_char, ctx: *const c_void) -> c_int {
+    unsafe {
+        let lines = &mut *(ctx as *mut Vec<String>);
+        let line = CStr::from_ptr(msg).to_string_lossy();
+        lines.push(line.trim_end_matches('\n').to_owned());
+    }
+    0
+}
+
+/// Map size used by LMDB if none is set
+const DEFAULT_MAP_SIZE: u64 = 10_485_760;
+
+fn os_page_size() -> u64 {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 }
+}
+
+fn check_map_size(map_size: u64, max_map_size: Option<u64>) -> MdbResult<()> {
+    match max_map_size {
+        Some(max) if map_size > max => {
+            Err(MdbError::MapFull(format!("map size {} exceeds ceiling of {} bytes", map_size, max)))
+        },
+        _ => Ok(())
+    }
+}
+
 #[derive(Debug)]
 struct EnvHandle(*mut ffi::MDB_env);
 
 impl Drop for EnvHandle {
     fn drop(&mut self) {
         unsafe {
-            if self.0.is_null() {
+            if !self.0.is_null() {
                 ffi::mdb_env_close(self.0);
             }
         }
@@ -347,6 +496,10 @@ pub struct Environment {
     env: Arc<EnvHandle>,
     db_cache: Arc<Mutex<UnsafeCell<HashMap<String, ffi::MDB_dbi>>>>,
     is_readonly: bool, // true if opened in 'read-only' mode
+    is_write_map: bool, // true if opened with writable map, which forbids nesting
+    max_map_size: Option<u64>, // ceiling for map growth
+    active_txns: Arc<AtomicUsize>, // transactions in Normal state in this process
+    max_dbs: Option<usize>, // kept to reopen environment after compaction
 }
 
 impl Environment {
@@ -355,11 +508,15 @@ impl Environment {
         EnvBuilder::new()
     }
 
-    fn from_raw(env: *mut ffi::MDB_env, is_readonly: bool) -> Environment {
+    fn from_raw(env: *mut ffi::MDB_env, is_readonly: bool, is_write_map: bool, max_map_size: Option<u64>, max_dbs: Option<usize>) -> Environment {
         Environment {
             env: Arc::new(EnvHandle(env)),
             db_cache: Arc::new(Mutex::new(UnsafeCell::new(HashMap::new()))),
             is_readonly,
+            is_write_map,
+            max_map_size,
+            active_txns: Arc::new(AtomicUsize::new(0)),
+            max_dbs,
         }
     }
 
@@ -371,6 +528,31 @@ impl Environment {
         lift_mdb!(unsafe { ffi::mdb_reader_check(self.env.0, &mut dead as *mut c_int)}, dead)
     }
 
+    /// Lists reader lock table, one line per active reader slot as
+    /// formatted by LMDB (pid, thread id and txnid), preceded by a
+    /// header line. See [readers](#method.readers) for parsed variant
+    pub fn reader_list(&self) -> MdbResult<Vec<String>> {
+        let mut lines: Vec<String> = Vec::new();
+        lift_mdb!(unsafe {
+            ffi::mdb_reader_list(self.env.0, collect_reader_line, &mut lines as *mut Vec<String> as *const c_void)
+        }, lines)
+    }
+
+    /// Returns a snapshot of active reader slots, allows to find out
+    /// which process/thread holds an old snapshot
+    pub fn readers(&self) -> MdbResult<Vec<ReaderInfo>> {
+        let lines = self.reader_list()?;
+        // header and "(no active readers)" lines are skipped by parser
+        Ok(lines.iter().filter_map(|l| ReaderInfo::parse(l)).collect())
+    }
+
+    /// Returns snapshot of the oldest active reader, `None` if there
+    /// are no readers. Pages freed after it can't be reused, so a big
+    /// gap to `last_txnid` explains growth of free list
+    pub fn oldest_reader_txnid(&self) -> MdbResult<Option<u64>> {
+        Ok(self.readers()?.iter().filter_map(|r| r.txnid).min())
+    }
+
     /// Retrieve environment statistics
     pub fn stat(&self) -> MdbResult<ffi::MDB_stat> {
         let mut tmp: ffi::MDB_stat = unsafe { std::mem::zeroed() };
@@ -382,14 +564,234 @@ impl Environment {
         lift_mdb!(unsafe { ffi::mdb_env_info(self.env.0, &mut tmp)}, tmp)
     }
 
+    /// Returns id of the last committed transaction. It changes on
+    /// every write commit, so a cache could store it and compare
+    /// later to find out if data may have changed
+    pub fn snapshot_token(&self) -> MdbResult<u64> {
+        self.last_txnid()
+    }
+
+    /// Page size of environment. It is chosen on creation, so an
+    /// environment created on another system may differ from OS page size
+    pub fn page_size(&self) -> MdbResult<usize> {
+        Ok(self.stat()?.ms_psize as usize)
+    }
+
+    /// Same as [stat](#method.stat), wrapped to be printed
+    pub fn stat_pretty(&self) -> MdbResult<Stat> {
+        self.stat().map(Stat)
+    }
+
+    /// Same as [info](#method.info), wrapped to be printed
+    pub fn info_pretty(&self) -> MdbResult<EnvInfo> {
+        self.info().map(EnvInfo)
+    }
+
+    /// Id of the last committed transaction, cheap to poll for new
+    /// commits
+    pub fn last_txnid(&self) -> MdbResult<u64> {
+        Ok(self.info()?.me_last_txnid as u64)
+    }
+
+    /// Number of used slots in reader lock table
+    pub fn num_readers(&self) -> MdbResult<u32> {
+        Ok(self.info()?.me_numreaders)
+    }
+
+    /// Returns the size of data file which is actually used by
+    /// environment, i.e. `(last_pgno + 1) * page_size`.
+    ///
+    /// Note that it differs from [file_size](#method.file_size) as
+    /// data file may be larger due to preallocation and never shrinks,
+    /// while freed pages are still counted as used until they're reused.
+    pub fn disk_size(&self) -> MdbResult<u64> {
+        let info = self.info()?;
+        let stat = self.stat()?;
+        Ok((info.me_last_pgno as u64 + 1) * stat.ms_psize as u64)
+    }
+
+    /// Returns size of data file allocated on disk
+    pub fn file_size(&self) -> MdbResult<u64> {
+        use std::fs;
+
+        let path = self.path()?;
+        let data_path = if self.get_all_flags()?.contains(ENV_CREATE_NO_SUB_DIR) {
+            path
+        } else {
+            path.join("data.mdb")
+        };
+        fs::metadata(&data_path)
+            .map(|meta| meta.len())
+            .map_err(|_| MdbError::InvalidPath)
+    }
+
+    /// Returns number of pages on free list, i.e. pages which were
+    /// freed by committed transactions and wait to be reused
+    pub fn freelist_pages(&self) -> MdbResult<u64> {
+        let txn = self.get_reader()?;
+        let mut cursor: *mut ffi::MDB_cursor = ptr::null_mut();
+        // free list is kept in LMDB internal database with handle 0
+        try_mdb!(unsafe { ffi::mdb_cursor_open(txn.get_handle(), 0, &mut cursor) });
+
+        let mut key: ffi::MDB_val = unsafe { std::mem::zeroed() };
+        let mut data: ffi::MDB_val = unsafe { std::mem::zeroed() };
+        let mut pages = 0u64;
+        let res = loop {
+            match unsafe { ffi::mdb_cursor_get(cursor, &mut key, &mut data, ffi::MDB_cursor_op::MDB_NEXT) } {
+                // every record is a list of page numbers prefixed by its
+                // length, data in pages isn't guaranteed to be aligned
+                ffi::MDB_SUCCESS => pages += unsafe { ptr::read_unaligned(data.mv_data as *const size_t) } as u64,
+                ffi::MDB_NOTFOUND => break Ok(pages),
+                code => break Err(MdbError::new_with_code(code)),
+            }
+        };
+        unsafe { ffi::mdb_cursor_close(cursor); }
+        res
+    }
+
+    /// Returns ratio of free list pages to pages used by environment,
+    /// a value close to 1.0 means most of data file is unused and
+    /// [compact_in_place](#method.compact_in_place) would shrink it
+    pub fn fragmentation(&self) -> MdbResult<f64> {
+        let used_pages = self.disk_size()? / self.stat()?.ms_psize as u64;
+        Ok(self.freelist_pages()? as f64 / used_pages as f64)
+    }
+
+    /// Rewrites data file without free pages and reopens environment
+    /// with the same settings. Environment must not be shared, i.e.
+    /// there should be no clones of it and no live transactions, and
+    /// it must not be opened by other processes
+    pub fn compact_in_place(self, perms: u32) -> MdbResult<Environment> {
+        use std::fs;
+
+        if Arc::strong_count(&self.env) > 1 {
+            return Err(MdbError::StateError("can't compact environment which is shared".to_owned()));
+        }
+        let active = self.active_txns.load(Ordering::SeqCst);
+        if active > 0 {
+            return Err(MdbError::StateError(format!("can't compact environment while {} transaction(s) are active", active)));
+        }
+
+        let path = self.path()?;
+        let flags = self.get_all_flags()?;
+        let info = self.info()?;
+        let (data_path, tmp_path) = if flags.contains(ENV_CREATE_NO_SUB_DIR) {
+            (path.clone(), path.with_extension("compact"))
+        } else {
+            (path.join("data.mdb"), path.join("compact"))
+        };
+        let tmp_data_path = if flags.contains(ENV_CREATE_NO_SUB_DIR) {
+            tmp_path.clone()
+        } else {
+            fs::create_dir_all(&tmp_path).map_err(|_| MdbError::InvalidPath)?;
+            tmp_path.join("data.mdb")
+        };
+
+        self.copy_to_path_compact(&tmp_path)?;
+
+        let mut builder = EnvBuilder::new()
+            .flags(flags)
+            .max_readers(info.me_maxreaders as usize)
+            .map_size(info.me_mapsize as u64)
+            .autocreate_dir(false);
+        if let Some(max_dbs) = self.max_dbs {
+            builder = builder.max_dbs(max_dbs);
+        }
+        if let Some(max_map_size) = self.max_map_size {
+            builder = builder.max_map_size(max_map_size);
+        }
+        drop(self);
+
+        fs::rename(&tmp_data_path, &data_path).map_err(|_| MdbError::InvalidPath)?;
+        if tmp_path != tmp_data_path {
+            fs::remove_dir(&tmp_path).map_err(|_| MdbError::InvalidPath)?;
+        }
+        builder.open(&path, perms)
+    }
+
+    /// Counts pages of data file which are resident in memory, to
+    /// find out whether working set fits in RAM.
+    ///
+    /// LMDB exposes map address only for `ENV_CREATE_FIXED_MAP`, so
+    /// data file is mapped once more for a `mincore` query. Both maps
+    /// share page cache, so result is the same. Pages are counted in
+    /// OS page size
+    #[cfg(unix)]
+    pub fn resident_pages(&self) -> MdbResult<usize> {
+        let len = std::cmp::min(self.file_size()?, self.info()?.me_mapsize as u64) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+        let fd = self.get_fd()?;
+        let page_size = os_page_size() as usize;
+        let mut residency = vec![0u8; len.div_ceil(page_size)];
+
+        unsafe {
+            let addr = libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_SHARED, fd, 0);
+            if addr == libc::MAP_FAILED {
+                return Err(MdbError::StateError("failed to map data file".to_owned()));
+            }
+            let res = libc::mincore(addr, len, residency.as_mut_ptr() as *mut _);
+            libc::munmap(addr, len);
+            if res != 0 {
+                return Err(MdbError::StateError("mincore failed on data file".to_owned()));
+            }
+        }
+        Ok(residency.iter().filter(|&&page| page & 1 == 1).count())
+    }
+
+    /// Returns path environment was opened from. For environments opened
+    /// with `ENV_CREATE_NO_SUB_DIR` it is path of data file, otherwise
+    /// it is a directory
+    pub fn path(&self) -> MdbResult<PathBuf> {
+        let mut path: *const c_char = ptr::null();
+        try_mdb!(unsafe { ffi::mdb_env_get_path(self.env.0, &mut path as *mut *const c_char as *mut *mut c_char) });
+        if path.is_null() {
+            return Err(MdbError::InvalidPath);
+        }
+        let path = unsafe { CStr::from_ptr(path) };
+        path.to_str()
+            .map(PathBuf::from)
+            .map_err(|_| MdbError::InvalidPath)
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakEnvironment {
+        WeakEnvironment(Arc::downgrade(&self.env))
+    }
+
     /// Sync environment to disk
     pub fn sync(&self, force: bool) -> MdbResult<()> {
         lift_mdb!(unsafe { ffi::mdb_env_sync(self.env.0, if force {1} else {0})})
     }
 
+    pub(crate) fn txn_started(&self) {
+        self.active_txns.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn txn_finished(&self) {
+        self.active_txns.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Grows map to new_size. Fails if new_size is less than current
+    /// map size or if there are active transactions in this process.
+    /// Committed, aborted and reset transactions aren't active
+    pub fn grow_to(&self, new_size: u64) -> MdbResult<()> {
+        let current = self.info()?.me_mapsize as u64;
+        if new_size < current {
+            return Err(MdbError::StateError(format!("can't grow map to {} bytes, it's already {} bytes", new_size, current)));
+        }
+        let active = self.active_txns.load(Ordering::SeqCst);
+        if active > 0 {
+            return Err(MdbError::StateError(format!("can't grow map while {} transaction(s) are active", active)));
+        }
+        self.set_mapsize(new_size as usize)
+    }
+
     /// Sets map size.
     /// This can be called after [open](struct.EnvBuilder.html#method.open) if no transactions are active in this process.
+    /// Fails with `MapFull` if size exceeds [max_map_size](struct.EnvBuilder.html#method.max_map_size)
     pub fn set_mapsize(&self, map_size: usize) -> MdbResult<()> {
+        check_map_size(map_size as u64, self.max_map_size)?;
         lift_mdb!(unsafe { ffi::mdb_env_set_mapsize(self.env.0, map_size as size_t)})
     }
 
@@ -415,6 +817,11 @@ impl Environment {
         lift_mdb!(unsafe {ffi::mdb_env_get_flags(self.env.0, &mut flags)}, EnvCreateFlags::from_bits_truncate(flags))
     }
 
+    /// Returns true if environment was opened with `ENV_CREATE_WRITE_MAP`
+    pub fn is_write_map(&self) -> bool {
+        self.is_write_map
+    }
+
     pub fn get_maxreaders(&self) -> MdbResult<c_uint> {
         let mut max_readers: c_uint = 0;
         lift_mdb!(unsafe {
@@ -422,8 +829,21 @@ impl Environment {
         }, max_readers)
     }
 
+    /// Raw form of [max_key_size](#method.max_key_size), kept for
+    /// compatibility. Returns 0 if size couldn't be retrieved
     pub fn get_maxkeysize(&self) -> c_int {
-        unsafe {ffi::mdb_env_get_maxkeysize(self.env.0)}
+        self.max_key_size().map(|size| size as c_int).unwrap_or(0)
+    }
+
+    /// Maximum size of a key, which is also the maximum size of a
+    /// value in databases with DbAllowDups
+    pub fn max_key_size(&self) -> MdbResult<usize> {
+        let size = unsafe { ffi::mdb_env_get_maxkeysize(self.env.0) };
+        if size > 0 {
+            Ok(size as usize)
+        } else {
+            Err(MdbError::StateError(format!("invalid max key size {}", size)))
+        }
     }
 
     /// Creates a backup copy in specified file descriptor
@@ -431,6 +851,12 @@ impl Environment {
         lift_mdb!(unsafe { ffi::mdb_env_copyfd(self.env.0, fd) })
     }
 
+    /// Creates a compacted backup copy in specified file descriptor,
+    /// free pages are omitted, see [copy_to_path_compact](#method.copy_to_path_compact)
+    pub fn copy_to_fd_compact(&self, fd: ffi::mdb_filehandle_t) -> MdbResult<()> {
+        lift_mdb!(unsafe { ffi::mdb_env_copyfd2(self.env.0, fd, ffi::MDB_CP_COMPACT) })
+    }
+
     /// Gets file descriptor of this environment
     pub fn get_fd(&self) -> MdbResult<ffi::mdb_filehandle_t> {
         let mut fd = 0;
@@ -450,6 +876,40 @@ impl Environment {
         }
     }
 
+    /// Creates a backup copy in specified path like
+    /// [copy_to_path](#method.copy_to_path), but creates missing
+    /// directories first instead of failing. For `ENV_CREATE_NO_SUB_DIR`
+    /// environments path is a file, so only its parent is created
+    pub fn copy_to_path_create<P: AsRef<Path>>(&self, path: P) -> MdbResult<()> {
+        use std::fs;
+
+        let dir = if self.get_all_flags()?.contains(ENV_CREATE_NO_SUB_DIR) {
+            path.as_ref().parent()
+        } else {
+            Some(path.as_ref())
+        };
+        if let Some(dir) = dir.filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| {
+                error!("failed to create dir for copy: {}", e);
+                MdbError::InvalidPath
+            })?;
+        }
+        self.copy_to_path(path)
+    }
+
+    /// Creates a compacted backup copy in specified path. Unlike
+    /// [copy_to_path](#method.copy_to_path) it omits free pages and
+    /// renumbers all pages sequentially, so the copy is usually smaller
+    /// than the source, but it takes more CPU time
+    pub fn copy_to_path_compact<P: AsRef<Path>>(&self, path: P) -> MdbResult<()> {
+        let path_str = path.as_ref().to_str().ok_or(MdbError::InvalidPath)?;
+        let c_path = CString::new(path_str).map_err(|_| MdbError::InvalidPath)?;
+
+        unsafe {
+            lift_mdb!(ffi::mdb_env_copy2(self.env.0, c_path.as_ref().as_ptr(), ffi::MDB_CP_COMPACT))
+        }
+    }
+
     fn create_transaction<'a>(&'a self, parent: Option<NativeTransaction<'a>>, flags: c_uint) -> MdbResult<NativeTransaction<'a>> {
         let mut handle: *mut ffi::MDB_txn = ptr::null_mut();
         let parent_handle = match parent {
@@ -472,12 +932,68 @@ impl Environment {
             .and_then(|txn| Ok(Transaction::new_with_native(txn)))
     }
 
+    /// Runs f in a read-write transaction, which is committed if f
+    /// succeeds and aborted otherwise, so writes can't be lost by
+    /// forgetting to commit
+    pub fn transact<F, R>(&self, f: F) -> MdbResult<R>
+        where F: FnOnce(&Transaction) -> MdbResult<R> {
+        let txn = self.new_transaction()?;
+        match f(&txn) {
+            Ok(res) => txn.commit().map(|_| res),
+            Err(e) => {
+                txn.abort();
+                Err(e)
+            }
+        }
+    }
+
+    /// Runs f in a read-write transaction like [transact](#method.transact),
+    /// retrying up to max_retries times if it fails with `MapResized`
+    /// or `BadTxn`. In case of `MapResized`, map grown by another
+    /// process is adopted before retry
+    pub fn with_retrying_write_txn<F, T>(&self, max_retries: usize, f: F) -> MdbResult<T>
+        where F: Fn(&Transaction) -> MdbResult<T> {
+        let mut retries = 0;
+        loop {
+            match self.transact(&f) {
+                Err(MdbError::MapResized) if retries < max_retries => {
+                    // zero picks up current size of data file
+                    self.set_mapsize(0)?;
+                },
+                Err(MdbError::BadTxn) if retries < max_retries => (),
+                res => return res
+            }
+            retries += 1;
+            debug!("retrying write transaction, attempt {}", retries);
+        }
+    }
+
+    /// Runs f in a read-write transaction which is always aborted,
+    /// i.e. checks whether a batch of writes would succeed without
+    /// changing anything. Returns result of f
+    pub fn dry_run<F, T>(&self, f: F) -> MdbResult<T>
+        where F: FnOnce(&Transaction) -> MdbResult<T> {
+        let txn = self.new_transaction()?;
+        let res = f(&txn);
+        txn.abort();
+        res
+    }
+
     /// Creates a readonly transaction
     pub fn get_reader<'a>(&'a self) -> MdbResult<ReadonlyTransaction<'a>> {
         self.create_transaction(None, ffi::MDB_RDONLY)
             .and_then(|txn| Ok(ReadonlyTransaction::new_with_native(txn)))
     }
 
+    /// Starts a background writer thread which batches writes queued
+    /// through returned `WriteSender` (which could be cloned and shared
+    /// between threads) into write transactions. As it is the only
+    /// writer, it must not be mixed with writes from other transactions
+    /// which would block it.
+    pub fn writer_queue(&self) -> (WriteSender, WriterHandle) {
+        writer::writer_queue(self.clone())
+    }
+
     fn _open_db(&self, db_name: & str, flags: DbFlags, force_creation: bool) -> MdbResult<ffi::MDB_dbi> {
         // debug!("Opening {} (create={}, read_only={})", db_name, force_creation, self.is_readonly);
         // From LMDB docs for mdb_dbi_open:
@@ -530,12 +1046,41 @@ impl Environment {
         }
     }
 
-    /// Opens existing DB
+    /// Returns statistics of a named db, empty name means default db.
+    /// Works in read-only transactions. Db handle is taken from cache
+    /// or opened in txn without being cached
+    pub fn db_stat<'txn>(&self, db_name: &str, txn: &dyn Txn<'txn>) -> MdbResult<ffi::MDB_stat> {
+        let cached = match self.db_cache.lock() {
+            Err(_) => return Err(MdbError::CacheError),
+            Ok(guard) => unsafe { (*guard.get()).get(db_name).cloned() },
+        };
+
+        let db = match cached {
+            Some(db) => db,
+            None => {
+                let mut db: ffi::MDB_dbi = 0;
+                let c_name = CString::new(db_name.as_bytes()).map_err(|_| MdbError::StateError("db name contains nul byte".to_owned()))?;
+                let name_ptr = if db_name.is_empty() { ptr::null() } else { c_name.as_ptr() };
+                try_mdb!(unsafe { ffi::mdb_dbi_open(txn.get_handle(), name_ptr, 0, &mut db) });
+                db
+            }
+        };
+        Database::new_with_handle(db).stat(txn)
+    }
+
+    /// Opens existing DB, `DB_CREATE` in flags is ignored. Returns
+    /// `NotFound` if named DB doesn't exist
     pub fn get_db(& self, db_name: &str, flags: DbFlags) -> MdbResult<Database> {
         let db = self._open_db(db_name, flags, false)?;
         Ok(Database::new_with_handle(db))
     }
 
+    /// Same as [get_db](#method.get_db), for call sites which want to
+    /// say explicitly that DB is never created
+    pub fn open_existing_db(&self, db_name: &str, flags: DbFlags) -> MdbResult<Database> {
+        self.get_db(db_name, flags)
+    }
+
     /// Opens or creates a DB
     pub fn create_db(&self, db_name: &str, flags: DbFlags) -> MdbResult<Database> {
         let db = self._open_db(db_name, flags, true)?;
@@ -547,6 +1092,148 @@ impl Environment {
         self.get_db("", flags)
     }
 
+    /// Opens default db in txn, unlike [get_default_db](#method.get_default_db)
+    /// doesn't start a transaction of its own, so it's safe to call
+    /// while txn holds the write lock
+    fn default_db_in<'txn>(&self, txn: &dyn Txn<'txn>) -> MdbResult<Database> {
+        let mut db: ffi::MDB_dbi = 0;
+        try_mdb!(unsafe { ffi::mdb_dbi_open(txn.get_handle(), ptr::null(), 0, &mut db) });
+        Ok(Database::new_with_handle(db))
+    }
+
+    /// Number of named databases, i.e. number of records in default
+    /// database. It's correct only if nothing is stored in default
+    /// database directly, see [iter_default_data](#method.iter_default_data)
+    /// for a slower check
+    pub fn named_db_count<'txn>(&self, txn: &dyn Txn<'txn>) -> MdbResult<usize> {
+        let default_db = self.default_db_in(txn)?;
+        Ok(default_db.stat(txn)?.ms_entries)
+    }
+
+    /// Names of named databases, i.e. keys of default database.
+    /// Same as [named_db_count](#method.named_db_count) it's correct
+    /// only if nothing is stored in default database directly
+    pub fn named_db_names<'txn>(&self, txn: &dyn Txn<'txn>) -> MdbResult<Vec<String>> {
+        let default_db = self.default_db_in(txn)?;
+        let names = default_db.keys::<Vec<u8>>(txn)?
+            .into_iter()
+            .map(|name| String::from_utf8_lossy(&name).into_owned())
+            .collect();
+        Ok(names)
+    }
+
+    /// Discovers named databases by probing keys of default database
+    /// in txn. Keys which couldn't be opened as a database, including
+    /// ones with nul bytes, are skipped. Probed handles aren't cached,
+    /// a reader txn releases them once it's finished
+    fn named_dbs<'txn>(&self, txn: &dyn Txn<'txn>) -> MdbResult<Vec<(String, Database)>> {
+        let names: Vec<String> = self.default_db_in(txn)?.iter(txn)?
+            .filter_map(|cv| String::from_utf8(cv.get_key::<Vec<u8>>()).ok())
+            .collect();
+
+        let mut dbs = Vec::new();
+        for name in names {
+            if let Some(db) = Environment::probe_db(&name, txn)? {
+                dbs.push((name, db));
+            }
+        }
+        Ok(dbs)
+    }
+
+    /// Opens named db in txn without caching. Returns `None` if name
+    /// can't be opened as a database: it's empty, has nul bytes,
+    /// doesn't exist or holds plain data
+    fn probe_db<'txn>(db_name: &str, txn: &dyn Txn<'txn>) -> MdbResult<Option<Database>> {
+        if db_name.is_empty() {
+            return Ok(None);
+        }
+        let c_name = match CString::new(db_name.as_bytes()) {
+            Ok(c_name) => c_name,
+            Err(_) => return Ok(None),
+        };
+        let mut db: ffi::MDB_dbi = 0;
+        match unsafe { ffi::mdb_dbi_open(txn.get_handle(), c_name.as_ptr(), 0, &mut db) } {
+            ffi::MDB_SUCCESS => Ok(Some(Database::new_with_handle(db))),
+            ffi::MDB_NOTFOUND | ffi::MDB_INCOMPATIBLE => Ok(None),
+            code => Err(MdbError::new_with_code(code))
+        }
+    }
+
+    /// Returns entries of default database which are not named
+    /// databases, i.e. only user data stored in it.
+    ///
+    /// Named databases are detected by trying to open every key as a
+    /// database, which is costly for large default databases. Every
+    /// named database is opened in a reader, so `max_dbs` has to be
+    /// large enough to fit all of them. Entries are copied as they
+    /// outlive reader
+    pub fn iter_default_data(&self) -> MdbResult<std::vec::IntoIter<(Vec<u8>, Vec<u8>)>> {
+        let txn = self.get_reader()?;
+        let names: Vec<String> = self.named_dbs(&txn)?.into_iter().map(|(name, _)| name).collect();
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = self.default_db_in(&txn)?.iter(&txn)?
+            .map(|cv| cv.get::<Vec<u8>, Vec<u8>>())
+            .filter(|(key, _)| !names.iter().any(|name| name.as_bytes() == &key[..]))
+            .collect();
+        Ok(entries.into_iter())
+    }
+
+    /// Drops all named databases which have no entries, for example
+    /// ones left half-initialized after a crash. Returns names of
+    /// dropped databases.
+    ///
+    /// This is destructive: an intentionally empty database is
+    /// dropped as well. Every named database is opened, so `max_dbs`
+    /// has to be large enough to fit all of them. Empty databases are
+    /// found in a reader and checked again before being dropped
+    pub fn gc_empty_dbs(&self) -> MdbResult<Vec<String>> {
+        let mut empty = Vec::new();
+        {
+            let reader = self.get_reader()?;
+            for (name, db) in self.named_dbs(&reader)? {
+                if db.stat(&reader)?.ms_entries == 0 {
+                    empty.push(name);
+                }
+            }
+        }
+
+        let txn = self.new_transaction()?;
+        let mut dropped = Vec::new();
+        for name in empty {
+            if let Some(db) = Environment::probe_db(&name, &txn)? {
+                if db.stat(&txn)?.ms_entries == 0 {
+                    db.del_db(&txn)?;
+                    dropped.push(name);
+                }
+            }
+        }
+        txn.commit()?;
+        Ok(dropped)
+    }
+
+    /// Drops named db in txn and creates it again with flags, keeping
+    /// cache in sync. Name is looked up in cache, so db must have been
+    /// opened through this environment.
+    ///
+    /// LMDB closes the old handle as soon as db is dropped, so its
+    /// cache entry is removed then. The new handle isn't cached: if
+    /// txn is aborted LMDB discards it and db stays as it was, next
+    /// open looks the name up again
+    pub(crate) fn recreate_db<'txn>(&self, handle: ffi::MDB_dbi, flags: DbFlags, txn: &dyn Txn<'txn>) -> MdbResult<ffi::MDB_dbi> {
+        let guard = self.db_cache.lock().map_err(|_| MdbError::CacheError)?;
+        let cache = unsafe { &mut *guard.get() };
+        let name = match cache.iter().find(|&(_, v)| *v == handle) {
+            Some((name, _)) => name.clone(),
+            None => return Err(MdbError::StateError("database isn't opened through this environment".to_owned())),
+        };
+        let c_name = CString::new(name.as_bytes()).map_err(|_| MdbError::StateError("db name contains nul byte".to_owned()))?;
+
+        try_mdb!(unsafe { ffi::mdb_drop(txn.get_handle(), handle, 1) });
+        cache.remove(&name);
+        let mut db: ffi::MDB_dbi = 0;
+        try_mdb!(unsafe { ffi::mdb_dbi_open(txn.get_handle(), c_name.as_ptr(), (flags | DB_CREATE).bits(), &mut db) });
+        Ok(db)
+    }
+
     pub fn drop_db_from_cache(&self, handle: ffi::MDB_dbi) {
         match self.db_cache.lock() {
             Err(_) => (),
@@ -576,12 +1263,35 @@ impl Environment {
 unsafe impl Sync for Environment {}
 unsafe impl Send for Environment {}
 
+/// Reference to environment which doesn't keep it open
+#[derive(Clone)]
+pub(crate) struct WeakEnvironment(Weak<EnvHandle>);
+
+impl WeakEnvironment {
+    /// Flushes environment to disk, `None` if it's already closed
+    pub(crate) fn sync(&self, force: bool) -> Option<MdbResult<()>> {
+        self.0.upgrade().map(|env| {
+            lift_mdb!(unsafe { ffi::mdb_env_sync(env.0, if force {1} else {0})})
+        })
+    }
+
+    pub(crate) fn is_alive(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+}
+
+unsafe impl Send for WeakEnvironment {}
+
 impl Clone for Environment {
     fn clone(&self) -> Environment {
         Environment {
             env: self.env.clone(),
             db_cache: self.db_cache.clone(),
             is_readonly: self.is_readonly,
+            is_write_map: self.is_write_map,
+            max_map_size: self.max_map_size,
+            active_txns: self.active_txns.clone(),
+            max_dbs: self.max_dbs,
         }
     }
 }
\ No newline at end of file
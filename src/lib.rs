@@ -10,12 +10,15 @@ extern crate liblmdb_sys as ffi;
 
 pub use libc::c_int;
 pub use ffi::{mdb_filehandle_t, MDB_stat, MDB_envinfo, MDB_val};
-pub use environment::{EnvBuilder, Environment, EnvFlags, EnvCreateFlags};
-pub use database::{Database, DbFlags, DbHandle};
-pub use crate::core::{MdbError, MdbValue, MdbResult};
-pub use transaction::{Transaction, ReadonlyTransaction, Txn };
-pub use cursor::{Cursor, CursorValue, CursorIter, CursorKeyRangeIter, CursorIterator, IterateCursor};
-pub use traits::{FromMdbValue, ToMdbValue};
+pub use environment::{EnvBuilder, Environment, EnvFlags, EnvCreateFlags, ReaderInfo, compiled_max_key_size, read_one, version};
+pub use database::{Database, DbFlags, DbHandle, ResumableScan, Scan, ScanPage, TypedDatabase};
+pub use crate::core::{EnvInfo, MdbError, MdbValue, MdbResult, Plain, Pod, Stat};
+pub use transaction::{Transaction, ReadonlyTransaction, ReaderPool, ResetReader, Txn };
+pub use cursor::{Cursor, CursorValue, CursorIter, CursorKeyRangeIter, CursorRangeIter, CursorIterator, CursorMap, CursorPrefixIter, CursorResults, CursorStream, CursorSuffixIter, DrainIter, IterateCursor, ReversibleCursor};
+pub use traits::{FromMdbValue, ToMdbValue, TryFromMdbValue};
+pub use writer::{WriteSender, WriterHandle};
+pub use scheduler::SyncScheduler;
+pub use compare::IntWidth;
 
 #[macro_use]
 pub mod core;
@@ -24,6 +27,9 @@ pub mod transaction;
 pub mod database;
 pub mod cursor;
 pub mod traits;
+pub mod writer;
+pub mod scheduler;
+pub mod compare;
 mod utils;
 
 #[cfg(test)]
@@ -0,0 +1,121 @@
+//! Background syncing of many environments from a single thread
+//!
+//! Environments opened with `ENV_NO_SYNC` or `ENV_NO_META_SYNC` need
+//! to be flushed periodically. Instead of spawning a thread per
+//! environment they could be registered with a `SyncScheduler`, which
+//! syncs each of them on its own interval. Scheduler doesn't keep
+//! environments open, dropped ones are unregistered automatically.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::environment::{Environment, WeakEnvironment};
+
+struct Entry {
+    env: WeakEnvironment,
+    interval: Duration,
+    next_sync: Instant,
+}
+
+struct State {
+    entries: Vec<Entry>,
+    stopped: bool,
+}
+
+type Shared = Arc<(Mutex<State>, Condvar)>;
+
+/// Syncs registered environments from a single background thread,
+/// which stops once scheduler is dropped
+pub struct SyncScheduler {
+    shared: Shared,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SyncScheduler {
+    /// Creates scheduler with no environments and starts its thread
+    pub fn new() -> SyncScheduler {
+        let shared: Shared = Arc::new((Mutex::new(State { entries: Vec::new(), stopped: false }), Condvar::new()));
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || run(&thread_shared));
+
+        SyncScheduler {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Registers environment to be synced every interval
+    pub fn register(&self, env: &Environment, interval: Duration) {
+        let (ref lock, ref cvar) = *self.shared;
+        let mut state = lock.lock().unwrap();
+        state.entries.push(Entry {
+            env: env.downgrade(),
+            interval,
+            next_sync: Instant::now() + interval,
+        });
+        cvar.notify_one();
+    }
+
+    /// Number of registered environments which are still open
+    pub fn registered(&self) -> usize {
+        let (ref lock, _) = *self.shared;
+        let state = lock.lock().unwrap();
+        state.entries.iter().filter(|e| e.env.is_alive()).count()
+    }
+}
+
+impl Default for SyncScheduler {
+    fn default() -> SyncScheduler {
+        SyncScheduler::new()
+    }
+}
+
+impl Drop for SyncScheduler {
+    fn drop(&mut self) {
+        {
+            let (ref lock, ref cvar) = *self.shared;
+            lock.lock().unwrap().stopped = true;
+            cvar.notify_one();
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(shared: &Shared) {
+    let (ref lock, ref cvar) = **shared;
+    let mut state = lock.lock().unwrap();
+
+    while !state.stopped {
+        let now = Instant::now();
+        // environments which are gone are unregistered
+        state.entries.retain(|e| e.env.is_alive());
+        let mut due = Vec::new();
+        for entry in state.entries.iter_mut() {
+            if entry.next_sync <= now {
+                due.push(entry.env.clone());
+                entry.next_sync = now + entry.interval;
+            }
+        }
+
+        // ~ lock isn't held while syncing, so registering and
+        // stopping don't wait for fsync
+        if !due.is_empty() {
+            drop(state);
+            for env in due {
+                if let Some(Err(e)) = env.sync(true) {
+                    warn!("Failed to sync environment: {:?}", e);
+                }
+            }
+            state = lock.lock().unwrap();
+            continue;
+        }
+
+        state = match state.entries.iter().map(|e| e.next_sync).min() {
+            Some(next) => cvar.wait_timeout(state, next.saturating_duration_since(now)).unwrap().0,
+            None => cvar.wait(state).unwrap(),
+        };
+    }
+}
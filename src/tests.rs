@@ -4,14 +4,19 @@ use std::path::{PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Once, ONCE_INIT};
 use std::thread;
+use std::time::Duration;
 
 use libc::c_int;
 
 use crate::core::{MdbValue, KeyExists, MdbError};
 use crate::environment::{self, EnvBuilder, ENV_NO_MEM_INIT, ENV_NO_META_SYNC };
-use crate::database::{self, DbFlags};
+use crate::database::{self, DbFlags, ResumableScan};
 use ffi::MDB_val;
-use crate::traits::FromMdbValue;
+use crate::transaction::{NativeTransaction, ReaderPool, Txn};
+use crate::cursor::CursorValue;
+use crate::scheduler::SyncScheduler;
+use crate::compare::{self, IntWidth};
+use crate::traits::{FromMdbValue, ToMdbValue};
 
 const USER_DIR: u32 = 0o777;
 static TEST_ROOT_DIR: &'static str = "test-dbs";
@@ -208,8 +213,6 @@ fn test_insert_values() {
 
 #[test]
 fn test_resize_map() {
-    use ffi::MDB_MAP_FULL;
-    
     let env = EnvBuilder::new()
         .max_dbs(5)
         .map_size(0x1000u64)
@@ -236,7 +239,7 @@ fn test_resize_map() {
     // write data until running into 'MDB_MAP_FULL' error
     loop {
         match write_closure() {
-            Err(MdbError::Other(MDB_MAP_FULL, _)) => { break; }
+            Err(MdbError::MapFull(_)) => { break; }
             Err(e) => panic!("unexpected db error {}", e),
             _ => {} // continue
         }
@@ -1048,3 +1051,2591 @@ fn test_compilation_of_moved_items() {
     })
 }
 */
+
+#[test]
+fn test_nonzero_conversion() {
+    use std::num::NonZeroU64;
+
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        let id = NonZeroU64::new(42).unwrap();
+        db.set(&"id", &id, &txn).unwrap();
+        assert_eq!(id, db.get::<NonZeroU64>(&"id", &txn).unwrap());
+        assert_eq!(id, db.try_get::<NonZeroU64>(&"id", &txn).unwrap());
+
+        // ~ a zero stored under the key must be rejected on checked read
+        db.set(&"zero", &0u64, &txn).unwrap();
+        match db.try_get::<NonZeroU64>(&"zero", &txn) {
+            Err(MdbError::Conversion(_)) => (),
+            other => panic!("Expected Conversion error, got {:?}", other)
+        }
+    }
+}
+
+#[test]
+fn test_bool_conversion() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        db.set(&"yes", &true, &txn).unwrap();
+        db.set(&"no", &false, &txn).unwrap();
+        assert!(db.get::<bool>(&"yes", &txn).unwrap());
+        assert!(!db.try_get::<bool>(&"no", &txn).unwrap());
+
+        // ~ a byte other than 0 or 1 isn't a bool
+        db.set(&"two", &2u8, &txn).unwrap();
+        assert!(matches!(db.try_get::<bool>(&"two", &txn), Err(MdbError::Conversion(_))));
+    }
+}
+
+#[test]
+#[should_panic(expected = "isn't a valid bool")]
+fn test_bool_conversion_panics() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&"two", &2u8, &txn).unwrap();
+    let _ = db.get::<bool>(&"two", &txn);
+}
+
+#[test]
+fn test_readers() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&"key", &"value", &txn).unwrap();
+    txn.commit().unwrap();
+
+    let reader = env.get_reader().unwrap();
+    let last_txnid = env.info().unwrap().me_last_txnid as u64;
+    let readers = env.readers().unwrap();
+    let pid = std::process::id() as i32;
+    assert!(readers.iter().any(|r| r.pid == pid && r.txnid.is_some_and(|t| t > 0 && t <= last_txnid)),
+            "No reader for current process in {:?}", readers);
+    drop(reader);
+}
+
+#[test]
+fn test_iter_results() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_INT_KEY).unwrap();
+    let recs: Vec<(u64, u64)> = vec![(1, 10), (2, 20), (3, 30)];
+
+    let txn = env.new_transaction().unwrap();
+    {
+        for &(k, v) in recs.iter() {
+            db.set(&k, &v, &txn).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let txn = env.get_reader().unwrap();
+    {
+        let mut iter = db.iter(&txn).unwrap().results();
+        let mut res = Vec::new();
+        for cv in iter.by_ref() {
+            res.push(cv.unwrap().get::<u64, u64>());
+        }
+        assert_eq!(res, recs);
+        assert!(iter.next().is_none(), "Iterator should stay exhausted");
+    }
+}
+
+#[test]
+fn test_disk_size() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_INT_KEY).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        for i in 0..1000u64 {
+            db.set(&i, &i, &txn).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let used = env.disk_size().unwrap();
+    assert!(used > 0);
+    assert!(used <= env.info().unwrap().me_mapsize as u64);
+    assert!(env.file_size().unwrap() > 0);
+}
+
+#[test]
+fn test_writer_queue() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+
+    let (sender, writer) = env.writer_queue();
+    let producers: Vec<_> = (0..3u32).map(|t| {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            for i in 0..100u32 {
+                let key = format!("{}-{}", t, i).into_bytes();
+                sender.send("queue", key, i.to_be_bytes().to_vec()).unwrap();
+            }
+        })
+    }).collect();
+    drop(sender);
+
+    for p in producers {
+        p.join().unwrap();
+    }
+    assert_eq!(writer.join().unwrap(), 300);
+
+    let db = env.get_db("queue", DbFlags::empty()).unwrap();
+    let txn = env.get_reader().unwrap();
+    assert_eq!(db.stat(&txn).unwrap().ms_entries, 300);
+    let v: Vec<u8> = db.get(&"2-99".to_owned().into_bytes(), &txn).unwrap();
+    assert_eq!(v, 99u32.to_be_bytes().to_vec());
+}
+
+#[test]
+fn test_db_equals() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_ALLOW_DUPS).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        for &(k, v) in [("a", "1"), ("a", "2"), ("b", "3"), ("c", "4"), ("c", "5")].iter() {
+            db.set(&k, &v, &txn).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let copy_path = next_path();
+    fs::create_dir_all(&copy_path).unwrap();
+    env.copy_to_path(&copy_path).unwrap();
+
+    let copy_env = EnvBuilder::new().open(&copy_path, USER_DIR).unwrap();
+    let copy_db = copy_env.get_default_db(database::DB_ALLOW_DUPS).unwrap();
+    {
+        let txn = env.get_reader().unwrap();
+        let copy_txn = copy_env.get_reader().unwrap();
+        assert!(db.equals(&copy_db, &txn, &copy_txn).unwrap());
+    }
+
+    // ~ an extra item for existing key has to be detected
+    let copy_txn = copy_env.new_transaction().unwrap();
+    copy_db.set(&"c", &"6", &copy_txn).unwrap();
+    copy_txn.commit().unwrap();
+    {
+        let txn = env.get_reader().unwrap();
+        let copy_txn = copy_env.get_reader().unwrap();
+        assert!(!db.equals(&copy_db, &txn, &copy_txn).unwrap());
+    }
+}
+
+#[test]
+fn test_pop_first_last() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_INT_KEY).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        for k in 1..4u64 {
+            db.set(&k, &(k * 10), &txn).unwrap();
+        }
+
+        assert_eq!(db.pop_first::<u64, u64>(&txn).unwrap(), Some((1, 10)));
+        assert_eq!(db.pop_first::<u64, u64>(&txn).unwrap(), Some((2, 20)));
+        assert_eq!(db.pop_first::<u64, u64>(&txn).unwrap(), Some((3, 30)));
+        assert_eq!(db.pop_first::<u64, u64>(&txn).unwrap(), None);
+
+        for k in 1..3u64 {
+            db.set(&k, &(k * 10), &txn).unwrap();
+        }
+        assert_eq!(db.pop_last::<u64, u64>(&txn).unwrap(), Some((2, 20)));
+        assert_eq!(db.pop_last::<u64, u64>(&txn).unwrap(), Some((1, 10)));
+        assert_eq!(db.pop_last::<u64, u64>(&txn).unwrap(), None);
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_version() {
+    let (major, minor, _, version) = environment::version();
+    assert_eq!(major, 0);
+    assert_eq!(minor, 9);
+    assert!(!version.is_empty());
+}
+
+#[test]
+fn test_max_map_size() {
+    let max_map_size = 0x40000u64;
+    let env = EnvBuilder::new()
+        .map_size(0x10000u64)
+        .max_map_size(max_map_size)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let test_data: [u8; 0xFF] = [0x5A; 0xFF];
+    let mut map_size = 0x10000usize;
+    let mut key_idx = 0u64;
+
+    // ~ keep writing and doubling map on MDB_MAP_FULL until growth is refused
+    let err = loop {
+        let txn = env.new_transaction().unwrap();
+        let res = db.set(&key_idx, &(&test_data[..]), &txn).and_then(|_| txn.commit());
+        match res {
+            Ok(_) => key_idx += 1,
+            Err(MdbError::MapFull(_)) => {
+                map_size *= 2;
+                if let Err(e) = env.set_mapsize(map_size) {
+                    break e;
+                }
+            },
+            Err(e) => panic!("unexpected db error {}", e)
+        }
+    };
+
+    match err {
+        MdbError::MapFull(ref msg) => assert!(msg.contains(&max_map_size.to_string()), "{}", msg),
+        e => panic!("Expected MapFull error, got {:?}", e)
+    }
+    assert!(key_idx > 0);
+}
+
+#[test]
+fn test_max_map_size_below_default() {
+    // ~ LMDB default map size is checked against ceiling as well
+    let res = EnvBuilder::new()
+        .max_map_size(0x40000u64)
+        .open(&next_path(), USER_DIR);
+    match res {
+        Err(MdbError::MapFull(ref msg)) => assert!(msg.contains("262144"), "{}", msg),
+        other => panic!("Expected MapFull error, got {:?}", other.map(|_| ()))
+    }
+
+    assert!(EnvBuilder::new()
+        .max_map_size(0x40000000u64)
+        .open(&next_path(), USER_DIR)
+        .is_ok());
+}
+
+#[test]
+fn test_take() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_ALLOW_DUPS).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        db.set(&"job", &"first", &txn).unwrap();
+        db.set(&"job", &"second", &txn).unwrap();
+        db.set(&"other", &"value", &txn).unwrap();
+
+        assert_eq!(db.take::<_, String>(&"other", &txn).unwrap(), Some("value".to_owned()));
+        assert_eq!(db.take::<_, String>(&"other", &txn).unwrap(), None);
+
+        // ~ only first item is taken for dup key
+        assert_eq!(db.take::<_, String>(&"job", &txn).unwrap(), Some("first".to_owned()));
+        assert_eq!(db.take::<_, String>(&"job", &txn).unwrap(), Some("second".to_owned()));
+        assert_eq!(db.take::<_, String>(&"job", &txn).unwrap(), None);
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_open_existing_db() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+
+    match env.open_existing_db("missing", DbFlags::empty()) {
+        Err(MdbError::NotFound) => (),
+        other => panic!("Expected NotFound, got {:?}", other)
+    }
+
+    // ~ DB_CREATE is ignored by both
+    assert!(matches!(env.get_db("missing", database::DB_CREATE), Err(MdbError::NotFound)));
+    assert!(matches!(env.open_existing_db("missing", database::DB_CREATE), Err(MdbError::NotFound)));
+
+    env.create_db("existing", DbFlags::empty()).unwrap();
+    assert!(env.open_existing_db("existing", database::DB_CREATE).is_ok());
+    // ~ must not have been created by the failed attempts
+    assert!(env.open_existing_db("missing", DbFlags::empty()).is_err());
+}
+
+#[test]
+fn test_scan_resume() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        for i in 0..1000u32 {
+            db.set(&format!("key-{:04}", i), &i, &txn).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = {
+        let txn = env.get_reader().unwrap();
+        let entries = db.iter(&txn).unwrap().map(|cv| cv.get::<Vec<u8>, Vec<u8>>()).collect();
+        entries
+    };
+    assert_eq!(expected.len(), 1000);
+
+    let mut scan = ResumableScan::new();
+    let mut scanned = Vec::new();
+    let mut pages = 0;
+    while !scan.is_finished() {
+        scanned.extend(scan.next_page(&db, &env, 100).unwrap());
+        pages += 1;
+    }
+    assert_eq!(scanned, expected);
+    assert_eq!(pages, 10);
+
+    // zero limit can't make progress, so it's rejected instead of
+    // looking like the end of data
+    assert!(matches!(db.scan_resume(&env, None, 0), Err(MdbError::StateError(_))));
+    let mut scan = ResumableScan::new();
+    assert!(scan.next_page(&db, &env, 0).is_err());
+    assert!(!scan.is_finished());
+    assert_eq!(scan.next_page(&db, &env, 1).unwrap(), &expected[..1]);
+}
+
+#[test]
+fn test_env_close_on_drop() {
+    let path = next_path();
+    {
+        let env = EnvBuilder::new().max_readers(1).open(&path, USER_DIR).unwrap();
+        let db = env.get_default_db(DbFlags::empty()).unwrap();
+        let txn = env.new_transaction().unwrap();
+        db.set(&"key", &"value", &txn).unwrap();
+        txn.commit().unwrap();
+
+        // ~ takes the only reader slot of this thread
+        let reader = env.get_reader().unwrap();
+        assert_eq!(db.get::<&str>(&"key", &reader).unwrap(), "value");
+    }
+
+    // ~ environment has to be closed by now, releasing its handles
+    #[cfg(target_os = "linux")]
+    {
+        let open_fds = fs::read_dir("/proc/self/fd").unwrap()
+            .filter_map(|fd| fs::read_link(fd.unwrap().path()).ok())
+            .filter(|target| target.starts_with(&path))
+            .count();
+        assert_eq!(open_fds, 0);
+    }
+
+    // ~ and its reader slot, so a new one could be taken
+    let env = EnvBuilder::new().max_readers(1).open(&path, USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.get_reader().unwrap();
+    assert_eq!(db.get::<&str>(&"key", &txn).unwrap(), "value");
+    assert_eq!(env.readers().unwrap().len(), 1);
+}
+
+#[test]
+fn test_env_path() {
+    let path = next_path();
+    let env = EnvBuilder::new().open(&path, USER_DIR).unwrap();
+    assert_eq!(env.path().unwrap(), path);
+
+    let dir = next_path();
+    fs::create_dir_all(&dir).unwrap();
+    let file_path = dir.join("data.db");
+    let env = EnvBuilder::new()
+        .flags(environment::ENV_CREATE_NO_SUB_DIR)
+        .open(&file_path, USER_DIR)
+        .unwrap();
+    assert_eq!(env.path().unwrap(), file_path);
+}
+
+#[test]
+fn test_gc_empty_dbs() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+
+    env.create_db("empty", DbFlags::empty()).unwrap();
+    let full = env.create_db("full", database::DB_ALLOW_DUPS).unwrap();
+    // ~ plain entries of default db must be skipped
+    let default_db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    full.set(&"key", &"value", &txn).unwrap();
+    default_db.set(&"plain", &"value", &txn).unwrap();
+    default_db.set(&"nul\0key", &"value", &txn).unwrap();
+    txn.commit().unwrap();
+
+    assert_eq!(env.gc_empty_dbs().unwrap(), vec!["empty".to_owned()]);
+    assert_eq!(env.gc_empty_dbs().unwrap(), Vec::<String>::new());
+    assert!(env.open_existing_db("empty", DbFlags::empty()).is_err());
+    assert!(env.open_existing_db("full", database::DB_ALLOW_DUPS).is_ok());
+}
+
+#[test]
+fn test_nth() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_INT_KEY).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        for k in 0..10u64 {
+            db.set(&k, &(k * 100), &txn).unwrap();
+        }
+        assert_eq!(db.nth::<u64, u64>(4, &txn).unwrap(), Some((4, 400)));
+        assert_eq!(db.nth::<u64, u64>(9, &txn).unwrap(), Some((9, 900)));
+        assert_eq!(db.nth::<u64, u64>(100, &txn).unwrap(), None);
+    }
+}
+
+#[test]
+fn test_crate_version() {
+    let (major, minor, patch, version) = crate::version();
+    assert!(version.contains(&format!("{}.{}.{}", major, minor, patch)), "{}", version);
+}
+
+#[test]
+fn test_retain() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let db = env.create_db("ints", database::DB_INT_KEY).unwrap();
+    let dups = env.create_db("dups", database::DB_ALLOW_DUPS).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        for k in 0..10u64 {
+            db.set(&k, &(k + 100), &txn).unwrap();
+        }
+
+        let deleted = db.retain(&txn, |_, v| {
+            let v = u64::from_mdb_value(&v.to_mdb_value());
+            v % 2 == 1
+        }).unwrap();
+        assert_eq!(deleted, 5);
+
+        let values: Vec<u64> = db.iter(&txn).unwrap().map(|cv| cv.get_value::<u64>()).collect();
+        assert_eq!(values, vec![101, 103, 105, 107, 109]);
+
+        // ~ a run of adjacent items up to the last one
+        let deleted = db.retain(&txn, |_, v| {
+            let v = u64::from_mdb_value(&v.to_mdb_value());
+            v < 105
+        }).unwrap();
+        assert_eq!(deleted, 3);
+        let values: Vec<u64> = db.iter(&txn).unwrap().map(|cv| cv.get_value::<u64>()).collect();
+        assert_eq!(values, vec![101, 103]);
+
+        // ~ items of the same key, including all items of one key
+        for key in ["a", "b", "c"].iter() {
+            for value in ["1", "2", "3", "4"].iter() {
+                dups.set(key, value, &txn).unwrap();
+            }
+        }
+        let deleted = dups.retain(&txn, |k, v| k != b"b" && v != b"2" && v != b"3").unwrap();
+        assert_eq!(deleted, 8);
+        let mut items: Vec<(String, String)> = Vec::new();
+        let mut cursor = dups.new_cursor(&txn).unwrap();
+        let mut res = cursor.move_to_first();
+        while res.is_ok() {
+            items.push(cursor.get().unwrap());
+            res = cursor.move_to_next();
+        }
+        assert_eq!(items, vec![("a".to_owned(), "1".to_owned()), ("a".to_owned(), "4".to_owned()),
+                               ("c".to_owned(), "1".to_owned()), ("c".to_owned(), "4".to_owned())]);
+    }
+}
+
+#[test]
+fn test_copy_compact() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_INT_KEY).unwrap();
+    let test_data: [u8; 0xFF] = [0x5A; 0xFF];
+
+    let txn = env.new_transaction().unwrap();
+    {
+        for k in 0..2000u64 {
+            db.set(&k, &(&test_data[..]), &txn).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        for k in 0..1000u64 {
+            db.del(&k, &txn).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let plain_path = next_path();
+    let compact_path = next_path();
+    fs::create_dir_all(&plain_path).unwrap();
+    fs::create_dir_all(&compact_path).unwrap();
+    env.copy_to_path(&plain_path).unwrap();
+    env.copy_to_path_compact(&compact_path).unwrap();
+
+    let plain_size = fs::metadata(plain_path.join("data.mdb")).unwrap().len();
+    let compact_size = fs::metadata(compact_path.join("data.mdb")).unwrap().len();
+    assert!(compact_size < plain_size, "compact {} >= plain {}", compact_size, plain_size);
+
+    // ~ compacted copy still has all remaining data
+    let copy_env = EnvBuilder::new().open(&compact_path, USER_DIR).unwrap();
+    let copy_db = copy_env.get_default_db(database::DB_INT_KEY).unwrap();
+    let txn = env.get_reader().unwrap();
+    let copy_txn = copy_env.get_reader().unwrap();
+    assert!(db.equals(&copy_db, &txn, &copy_txn).unwrap());
+}
+
+#[test]
+fn test_reader_list() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let pid = std::process::id().to_string();
+
+    let reader = env.get_reader().unwrap();
+    let lines = env.reader_list().unwrap();
+    assert!(lines.iter().any(|l| l.split_whitespace().next() == Some(&pid[..])), "{:?}", lines);
+    drop(reader);
+}
+
+#[test]
+fn test_del_default_db() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let named = env.create_db("named", DbFlags::empty()).unwrap();
+    assert!(db.is_default());
+    assert!(!named.is_default());
+
+    let txn = env.new_transaction().unwrap();
+    db.set(&"key", &"value", &txn).unwrap();
+    match db.clone().del_db(&txn) {
+        Err(MdbError::StateError(_)) => (),
+        other => panic!("Expected StateError, got {:?}", other)
+    }
+    assert!(named.del_db(&txn).is_ok());
+    assert!(db.clear(&txn).is_ok());
+    assert!(db.get::<()>(&"key", &txn).is_err());
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_suffix_iter() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+
+    let db = env.create_db("files", database::DB_REVERSE_KEY).unwrap();
+    let plain = env.create_db("plain", DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        for name in ["a.log", "b.log", "c.txt", "log"].iter() {
+            db.set(name, &"", &txn).unwrap();
+        }
+
+        let keys: Vec<String> = db.suffix_iter(b".log", &txn).unwrap().map(|cv| cv.get_key::<String>()).collect();
+        assert_eq!(keys, vec!["a.log".to_owned(), "b.log".to_owned()]);
+
+        let keys: Vec<String> = db.suffix_iter(b".bin", &txn).unwrap().map(|cv| cv.get_key::<String>()).collect();
+        assert!(keys.is_empty());
+
+        // every key ends with empty suffix
+        let keys: Vec<String> = db.suffix_iter(b"", &txn).unwrap().map(|cv| cv.get_key::<String>()).collect();
+        assert_eq!(keys, vec!["log".to_owned(), "a.log".to_owned(), "b.log".to_owned(), "c.txt".to_owned()]);
+    }
+
+    assert!(plain.suffix_iter(b".log", &txn).is_err());
+}
+
+#[test]
+fn test_typed_db() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap().typed::<String, u64>();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        let key = "answer".to_owned();
+        db.set(&key, &42, &txn).unwrap();
+        assert_eq!(db.get(&key, &txn).unwrap(), 42);
+
+        db.del(&key, &txn).unwrap();
+        assert!(db.get(&key, &txn).is_err());
+
+        // untyped access still works on same data
+        db.set(&key, &7, &txn).unwrap();
+        assert_eq!(db.untyped().get::<u64>(&"answer", &txn).unwrap(), 7);
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_write_count() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let dups = env.create_db("dups", database::DB_ALLOW_DUPS).unwrap();
+
+    let n = 100;
+    let txn = env.new_transaction().unwrap();
+    {
+        assert_eq!(txn.write_count(), 0);
+        for i in 0..n {
+            db.set(&(i as u64), &"value", &txn).unwrap();
+        }
+        assert_eq!(txn.write_count(), n);
+
+        // failed writes aren't counted
+        assert!(db.insert(&0u64, &"other", &txn).is_err());
+        assert!(db.del(&(n as u64), &txn).is_err());
+        assert_eq!(txn.write_count(), n);
+
+        db.del(&0u64, &txn).unwrap();
+        dups.set(&"key", &"a", &txn).unwrap();
+        dups.set(&"key", &"b", &txn).unwrap();
+        dups.del_item(&"key", &"a", &txn).unwrap();
+        assert_eq!(txn.write_count(), n + 4);
+
+        let mut cursor = db.new_cursor(&txn).unwrap();
+        cursor.move_to_first().unwrap();
+        cursor.del().unwrap();
+        cursor.set(&0u64, &"value", 0).unwrap();
+        assert_eq!(txn.write_count(), n + 6);
+        assert_eq!(txn.dirty_pages(), n + 6);
+    }
+    txn.commit().unwrap();
+
+    let reader = env.get_reader().unwrap();
+    assert_eq!(reader.write_count(), 0);
+    assert_eq!(reader.dirty_pages(), 0);
+}
+
+#[test]
+fn test_reserve() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let dups = env.create_db("dups", database::DB_ALLOW_DUPS).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        let buf = db.reserve(&"key", 4, &txn).unwrap();
+        assert_eq!(buf.len(), 4);
+        buf.copy_from_slice(b"data");
+
+        assert_eq!(db.get::<&[u8]>(&"key", &txn).unwrap(), b"data");
+        assert!(dups.reserve(&"key", 4, &txn).is_err());
+    }
+    assert_eq!(txn.bytes_written(), 7);
+    txn.commit().unwrap();
+
+    // reserved space counts against write limit
+    let mut txn = env.new_transaction().unwrap();
+    txn.set_write_limit(100);
+    assert!(db.reserve(&"big", 100, &txn).is_err());
+    assert_eq!(txn.bytes_written(), 0);
+    db.reserve(&"small", 10, &txn).unwrap();
+    assert_eq!(txn.bytes_written(), 15);
+}
+
+#[test]
+fn test_custom_txn_impl() {
+    // ~ Txn isn't sealed, writes through custom implementations work
+    // but aren't counted
+    #[derive(Debug)]
+    struct Wrapped<'a>(crate::transaction::Transaction<'a>);
+
+    impl<'a> Txn<'a> for Wrapped<'a> {
+        fn get_handle(&self) -> *mut ffi::MDB_txn { self.0.get_handle() }
+        fn get_env(&self) -> &'a environment::Environment { self.0.get_env() }
+        fn get_state(&self) -> crate::transaction::TransactionState { self.0.get_state() }
+    }
+
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = Wrapped(env.new_transaction().unwrap());
+    db.set(&"key", &"value", &txn).unwrap();
+    assert_eq!(db.get::<String>(&"key", &txn).unwrap(), "value");
+    assert_eq!(txn.write_count(), 0);
+    assert!(!txn.is_readonly());
+    assert_eq!(txn.0.write_count(), 0);
+    txn.0.commit().unwrap();
+}
+
+#[test]
+fn test_effective_map_size() {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 };
+
+    let builder = EnvBuilder::new().map_size(10 * page_size + 1);
+    assert_eq!(builder.effective_map_size(), 11 * page_size);
+
+    let builder = EnvBuilder::new().map_size(u64::MAX);
+    assert_eq!(builder.effective_map_size(), u64::MAX / page_size * page_size);
+
+    let builder = EnvBuilder::new().map_size(10 * page_size);
+    assert_eq!(builder.effective_map_size(), 10 * page_size);
+
+    let env = builder.open(&next_path(), USER_DIR).unwrap();
+    assert_eq!(env.info().unwrap().me_mapsize as u64, 10 * page_size);
+}
+
+#[test]
+fn test_get_flags() {
+    let path = next_path();
+    let flags = database::DB_ALLOW_DUPS | database::DB_INT_KEY;
+    {
+        let env = EnvBuilder::new().max_dbs(5).open(&path, USER_DIR).unwrap();
+        env.create_db("flagged", flags).unwrap();
+    }
+
+    let env = EnvBuilder::new().max_dbs(5).open(&path, USER_DIR).unwrap();
+    let db = env.get_db("flagged", DbFlags::empty()).unwrap();
+    let reader = env.get_reader().unwrap();
+    let stored = db.get_flags(&reader).unwrap();
+    assert!(stored.contains(flags));
+    assert!(!stored.contains(database::DB_REVERSE_KEY));
+}
+
+#[test]
+fn test_is_write_map() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    assert!(!env.is_write_map());
+
+    let env = EnvBuilder::new()
+        .flags(environment::ENV_CREATE_WRITE_MAP)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    assert!(env.is_write_map());
+}
+
+#[test]
+fn test_cursor_add_items() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let db = env.create_db("fixed", database::DB_ALLOW_DUPS | database::DB_DUP_FIXED).unwrap();
+    let plain = env.create_db("plain", database::DB_ALLOW_DUPS).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        let values: Vec<u32> = (0..100).collect();
+
+        let mut cursor = db.new_cursor(&txn).unwrap();
+        cursor.set(&"key", &values[0], 0).unwrap();
+        cursor.move_to_key(&"key").unwrap();
+        assert_eq!(cursor.add_items(&values[1..]).unwrap(), 99);
+        assert_eq!(cursor.add_items::<u32>(&[]).unwrap(), 0);
+
+        cursor.move_to_key(&"key").unwrap();
+        assert_eq!(cursor.item_count().unwrap(), 100);
+        let stored: Vec<u32> = db.item_iter(&"key", &txn).unwrap().map(|cv| cv.get_value::<u32>()).collect();
+        assert_eq!(stored, values);
+
+        let mut cursor = plain.new_cursor(&txn).unwrap();
+        cursor.set(&"key", &0u32, 0).unwrap();
+        cursor.move_to_key(&"key").unwrap();
+        assert!(cursor.add_items(&values).is_err());
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_cursor_get_multiple() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let db = env.create_db("fixed", database::DB_ALLOW_DUPS | database::DB_DUP_FIXED).unwrap();
+    let plain = env.create_db("plain", database::DB_ALLOW_DUPS).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        let values: Vec<u32> = (0..5000).collect();
+        let mut cursor = db.new_cursor(&txn).unwrap();
+        cursor.set(&"key", &values[0], 0).unwrap();
+        cursor.move_to_key(&"key").unwrap();
+        cursor.add_items(&values[1..]).unwrap();
+
+        let mut read: Vec<u32> = Vec::new();
+        cursor.move_to_key(&"key").unwrap();
+        read.extend_from_slice(cursor.get_multiple::<u32>().unwrap());
+        loop {
+            match cursor.get_next_multiple::<u32>() {
+                Ok(items) => read.extend_from_slice(items),
+                Err(MdbError::NotFound) => break,
+                Err(e) => panic!("unexpected error {:?}", e),
+            }
+        }
+        // items are sorted by their bytes, not as integers
+        read.sort();
+        assert_eq!(read, values);
+
+        plain.set(&"key", &0u32, &txn).unwrap();
+        let mut cursor = plain.new_cursor(&txn).unwrap();
+        cursor.move_to_key(&"key").unwrap();
+        match cursor.get_multiple::<u32>() {
+            Err(MdbError::Incompatible) => (),
+            other => panic!("expected Incompatible error, got {:?}", other),
+        }
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_cursor_dup_count() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let db = env.create_db("dups", database::DB_ALLOW_DUPS).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        for &(k, v) in [("a", "1"), ("a", "2"), ("a", "3"), ("b", "1"), ("b", "2")].iter() {
+            db.set(&k, &v, &txn).unwrap();
+        }
+
+        let mut cursor = db.new_cursor(&txn).unwrap();
+        cursor.move_to_first().unwrap();
+        cursor.move_to_next().unwrap();
+        assert_eq!(cursor.dup_count().unwrap(), 3);
+        assert_eq!(cursor.get::<&str, &str>().unwrap(), ("a", "2"));
+
+        cursor.move_to_next().unwrap();
+        assert_eq!(cursor.get::<&str, &str>().unwrap(), ("a", "3"));
+        cursor.move_to_next().unwrap();
+        assert_eq!(cursor.dup_count().unwrap(), 2);
+        cursor.move_to_next().unwrap();
+        assert_eq!(cursor.get::<&str, &str>().unwrap(), ("b", "2"));
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_sync_scheduler() {
+    let scheduler = SyncScheduler::new();
+    let paths = [next_path(), next_path()];
+
+    {
+        let fast = EnvBuilder::new().flags(environment::ENV_CREATE_NO_SYNC).open(&paths[0], USER_DIR).unwrap();
+        let slow = EnvBuilder::new().flags(environment::ENV_CREATE_NO_SYNC).open(&paths[1], USER_DIR).unwrap();
+        scheduler.register(&fast, Duration::from_millis(10));
+        scheduler.register(&slow, Duration::from_millis(50));
+        assert_eq!(scheduler.registered(), 2);
+
+        for env in [&fast, &slow].iter() {
+            let db = env.get_default_db(DbFlags::empty()).unwrap();
+            let txn = env.new_transaction().unwrap();
+            db.set(&"key", &"synced", &txn).unwrap();
+            txn.commit().unwrap();
+        }
+        thread::sleep(Duration::from_millis(150));
+
+        drop(slow);
+        assert_eq!(scheduler.registered(), 1);
+    }
+    assert_eq!(scheduler.registered(), 0);
+
+    for path in paths.iter() {
+        let env = EnvBuilder::new().open(path, USER_DIR).unwrap();
+        let db = env.get_default_db(DbFlags::empty()).unwrap();
+        let reader = env.get_reader().unwrap();
+        assert_eq!(db.get::<&str>(&"key", &reader).unwrap(), "synced");
+    }
+}
+
+#[test]
+fn test_iter_rev() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let db = env.create_db("plain", DbFlags::empty()).unwrap();
+    let dups = env.create_db("dups", database::DB_ALLOW_DUPS).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        for key in ["a", "b", "c", "d", "e"].iter() {
+            db.set(key, key, &txn).unwrap();
+            dups.set(key, &"1", &txn).unwrap();
+            dups.set(key, &"2", &txn).unwrap();
+        }
+
+        let keys = |it: &mut dyn Iterator<Item=CursorValue>| -> Vec<String> {
+            it.map(|cv| cv.get_key::<String>()).collect()
+        };
+
+        assert_eq!(keys(&mut db.iter(&txn).unwrap().rev()), vec!["e", "d", "c", "b", "a"]);
+        assert_eq!(keys(&mut db.keyrange(&"b", &"d", &txn).unwrap().rev()), vec!["d", "c", "b"]);
+        assert_eq!(keys(&mut db.keyrange_from_to(&"b", &"d", &txn).unwrap().rev()), vec!["c", "b"]);
+        assert_eq!(keys(&mut db.keyrange_from(&"bb", &txn).unwrap().rev()), vec!["e", "d", "c"]);
+        assert_eq!(keys(&mut db.keyrange_to(&"c", &txn).unwrap().rev()), vec!["b", "a"]);
+        assert_eq!(keys(&mut db.keyrange(&"x", &"z", &txn).unwrap().rev()), Vec::<String>::new());
+
+        // same items as moving forward
+        let values: Vec<(String, String)> = dups.iter(&txn).unwrap().rev()
+            .map(|cv| cv.get::<String, String>())
+            .collect();
+        assert_eq!(values[0], ("e".to_owned(), "1".to_owned()));
+        assert_eq!(values.len(), 5);
+
+        // directions may be mixed, iteration stops where they meet
+        let mut iter = db.iter(&txn).unwrap();
+        assert_eq!(iter.next().unwrap().get_key::<&str>(), "a");
+        assert_eq!(iter.next_back().unwrap().get_key::<&str>(), "e");
+        assert_eq!(iter.next_back().unwrap().get_key::<&str>(), "d");
+        assert_eq!(iter.next().unwrap().get_key::<&str>(), "b");
+        assert_eq!(iter.next().unwrap().get_key::<&str>(), "c");
+        assert!(iter.next().is_none());
+        assert!(iter.next_back().is_none());
+
+        let mut iter = db.keyrange(&"b", &"d", &txn).unwrap();
+        assert_eq!(iter.next_back().unwrap().get_key::<&str>(), "d");
+        assert_eq!(iter.next_back().unwrap().get_key::<&str>(), "c");
+        assert_eq!(iter.next().unwrap().get_key::<&str>(), "b");
+        assert!(iter.next_back().is_none());
+        assert!(iter.next().is_none());
+
+        // every key yielded once from either end
+        let mut iter = dups.iter(&txn).unwrap();
+        let mut seen = Vec::new();
+        loop {
+            match (iter.next(), iter.next_back()) {
+                (None, None) => break,
+                (front, back) => seen.extend(front.into_iter().chain(back).map(|cv| cv.get_key::<String>())),
+            }
+        }
+        seen.sort();
+        assert_eq!(seen, vec!["a", "b", "c", "d", "e"]);
+        assert_eq!(db.iter(&txn).unwrap().count(), 5);
+        let mut iter = db.iter(&txn).unwrap();
+        iter.next_back();
+        iter.next();
+        assert_eq!(iter.count(), 3);
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_range() {
+    use std::ops::Bound;
+
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let db = env.create_db("ints", database::DB_INT_KEY).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        for i in 0..30u64 {
+            db.set(&i, &i, &txn).unwrap();
+        }
+
+        let keys = |it: &mut dyn Iterator<Item=CursorValue>| -> Vec<u64> {
+            it.map(|cv| cv.get_key::<u64>()).collect()
+        };
+
+        assert_eq!(keys(&mut db.range(10u64..20, &txn).unwrap()), (10..20).collect::<Vec<u64>>());
+        assert_eq!(keys(&mut db.range(..=5u64, &txn).unwrap()), (0..6).collect::<Vec<u64>>());
+        assert_eq!(keys(&mut db.range(25u64.., &txn).unwrap()), (25..30).collect::<Vec<u64>>());
+        assert_eq!(keys(&mut db.range::<u64, _>(.., &txn).unwrap()).len(), 30);
+
+        // exclusive lower bound
+        let bounds: (Bound<u64>, Bound<u64>) = (Bound::Excluded(10), Bound::Included(12));
+        assert_eq!(keys(&mut db.range(bounds, &txn).unwrap()), vec![11, 12]);
+        let bounds: (Bound<u64>, Bound<u64>) = (Bound::Excluded(29), Bound::Unbounded);
+        assert!(keys(&mut db.range(bounds, &txn).unwrap()).is_empty());
+
+        assert_eq!(keys(&mut db.range(10u64..13, &txn).unwrap().rev()), vec![12, 11, 10]);
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_range_syntaxes() {
+    let env = EnvBuilder::new().max_dbs(1).open(&next_path(), USER_DIR).unwrap();
+    let db = env.create_db("even", database::DB_INT_KEY).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        // 0, 2, .., 18 so that bounds may fall both on and between keys
+        for i in (0..20u64).step_by(2) {
+            db.set(&i, &i, &txn).unwrap();
+        }
+
+        let keys = |it: &mut dyn Iterator<Item=CursorValue>| -> Vec<u64> {
+            it.map(|cv| cv.get_key::<u64>()).collect()
+        };
+
+        assert_eq!(keys(&mut db.range::<u64, _>(.., &txn).unwrap()), (0..20).step_by(2).collect::<Vec<u64>>());
+
+        assert_eq!(keys(&mut db.range(4u64..8, &txn).unwrap()), vec![4, 6]);
+        assert_eq!(keys(&mut db.range(3u64..9, &txn).unwrap()), vec![4, 6, 8]);
+        assert!(keys(&mut db.range(4u64..4, &txn).unwrap()).is_empty());
+
+        assert_eq!(keys(&mut db.range(4u64..=8, &txn).unwrap()), vec![4, 6, 8]);
+        assert_eq!(keys(&mut db.range(3u64..=9, &txn).unwrap()), vec![4, 6, 8]);
+        assert_eq!(keys(&mut db.range(4u64..=4, &txn).unwrap()), vec![4]);
+
+        assert_eq!(keys(&mut db.range(14u64.., &txn).unwrap()), vec![14, 16, 18]);
+        assert_eq!(keys(&mut db.range(15u64.., &txn).unwrap()), vec![16, 18]);
+        assert!(keys(&mut db.range(19u64.., &txn).unwrap()).is_empty());
+
+        assert_eq!(keys(&mut db.range(..4u64, &txn).unwrap()), vec![0, 2]);
+        assert_eq!(keys(&mut db.range(..5u64, &txn).unwrap()), vec![0, 2, 4]);
+        assert!(keys(&mut db.range(..0u64, &txn).unwrap()).is_empty());
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_grow_to() {
+    let env = EnvBuilder::new()
+        .map_size(10 * 1024 * 1024)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let size = env.info().unwrap().me_mapsize as u64;
+
+    assert!(env.grow_to(size / 2).is_err());
+
+    {
+        let _reader = env.get_reader().unwrap();
+        match env.grow_to(size * 2) {
+            Err(MdbError::StateError(msg)) => assert!(msg.contains("active")),
+            other => panic!("expected StateError, got {:?}", other),
+        }
+    }
+
+    env.grow_to(size * 2).unwrap();
+    assert_eq!(env.info().unwrap().me_mapsize as u64, size * 2);
+
+    // ~ reset readers don't hold a snapshot, so they don't block growing
+    let pool = ReaderPool::new(&env);
+    pool.with_reader(|_| ()).unwrap();
+    assert_eq!(pool.len(), 1);
+    env.grow_to(size * 3).unwrap();
+
+    let reset = env.get_reader().unwrap().into_reset();
+    env.grow_to(size * 4).unwrap();
+    let mut reader = reset.renew().unwrap();
+    assert!(env.grow_to(size * 5).is_err());
+
+    // ~ neither do aborted ones which weren't dropped yet
+    reader.abort();
+    env.grow_to(size * 5).unwrap();
+    assert_eq!(env.info().unwrap().me_mapsize as u64, size * 5);
+}
+
+#[test]
+fn test_keys() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let plain = env.create_db("plain", DbFlags::empty()).unwrap();
+    let dups = env.create_db("dups", database::DB_ALLOW_DUPS).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        for key in ["c", "a", "b"].iter() {
+            plain.set(key, &"value", &txn).unwrap();
+            dups.set(key, &"1", &txn).unwrap();
+            dups.set(key, &"2", &txn).unwrap();
+        }
+
+        let expected = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        for db in [&plain, &dups].iter() {
+            assert_eq!(db.distinct_key_count(&txn).unwrap(), 3);
+            let keys = db.keys::<String>(&txn).unwrap();
+            assert_eq!(keys, expected);
+            assert_eq!(keys.capacity(), 3);
+        }
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_reader_pool() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    {
+        let txn = env.new_transaction().unwrap();
+        db.set(&"key", &"value", &txn).unwrap();
+        txn.commit().unwrap();
+    }
+
+    let pool = ReaderPool::new(&env);
+    assert!(pool.is_empty());
+    for _ in 0..3 {
+        let value = pool.with_reader(|reader| db.get::<String>(&"key", reader).unwrap()).unwrap();
+        assert_eq!(value, "value");
+        assert_eq!(pool.len(), 1);
+    }
+
+    // ~ readers added from outside are reset and reused
+    pool.add(env.get_reader().unwrap());
+    assert_eq!(pool.len(), 2);
+    let value = pool.with_reader(|reader| db.get::<String>(&"key", reader).unwrap()).unwrap();
+    assert_eq!(value, "value");
+    assert_eq!(pool.len(), 2);
+}
+
+#[test]
+fn test_append_out_of_order() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        db.append(&"ab", &"1", &txn).unwrap();
+        db.append(&"cd", &"2", &txn).unwrap();
+        match db.append(&"b", &"3", &txn) {
+            Err(MdbError::AppendOutOfOrder(msg)) => {
+                // "b" is rejected after "cd"
+                assert!(msg.contains("0x62"));
+                assert!(msg.contains("0x6364"));
+            },
+            other => panic!("expected append error, got {:?}", other),
+        }
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_cursor_iter_from_current() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        for key in ["a", "b", "c", "d"].iter() {
+            db.set(key, key, &txn).unwrap();
+        }
+
+        let mut cursor = db.new_cursor(&txn).unwrap();
+        cursor.move_to_gte_key(&"b").unwrap();
+        let items: Vec<(Vec<u8>, Vec<u8>)> = cursor.iter_from_current().take(2).collect();
+        assert_eq!(items, vec![(b"b".to_vec(), b"b".to_vec()), (b"c".to_vec(), b"c".to_vec())]);
+
+        // cursor is usable again and stays where stream left it
+        assert_eq!(cursor.get_key::<&str>().unwrap(), "c");
+        let rest: Vec<(Vec<u8>, Vec<u8>)> = cursor.iter_from_current().collect();
+        assert_eq!(rest.len(), 2);
+
+        let mut cursor = db.new_cursor(&txn).unwrap();
+        assert_eq!(cursor.iter_from_current().count(), 0);
+
+        // ~ stream over unpositioned cursor stays exhausted
+        let mut cursor = db.new_cursor(&txn).unwrap();
+        let mut stream = cursor.iter_from_current();
+        assert!(stream.next().is_none());
+        assert!(stream.next().is_none());
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_incr() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        assert_eq!(db.incr(&"counter", 5, &txn).unwrap(), 5);
+        assert_eq!(db.incr(&"counter", 10, &txn).unwrap(), 15);
+        assert_eq!(db.incr(&"counter", -20, &txn).unwrap(), -5);
+        assert_eq!(db.get::<&[u8]>(&"counter", &txn).unwrap(), &(-5i64).to_le_bytes()[..]);
+
+        db.incr(&"big", i64::MAX, &txn).unwrap();
+        match db.incr(&"big", 1, &txn) {
+            Err(MdbError::StateError(msg)) => assert!(msg.contains("overflow")),
+            other => panic!("expected overflow error, got {:?}", other),
+        }
+        assert_eq!(db.incr(&"big", 0, &txn).unwrap(), i64::MAX);
+
+        db.set(&"text", &"abc", &txn).unwrap();
+        assert!(db.incr(&"text", 1, &txn).is_err());
+    }
+    txn.commit().unwrap();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_values() {
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        db.set_serde(&"point", &Point { x: 1, y: -2 }, &txn).unwrap();
+        assert_eq!(db.get_serde::<_, Point>(&"point", &txn).unwrap(), Point { x: 1, y: -2 });
+
+        db.set(&"short", &"x", &txn).unwrap();
+        match db.get_serde::<_, Point>(&"short", &txn) {
+            Err(MdbError::Conversion(_)) => (),
+            other => panic!("expected conversion error, got {:?}", other),
+        }
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_dump() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let db = env.create_db("dups", database::DB_ALLOW_DUPS).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        db.set(&"a", &"one", &txn).unwrap();
+        db.set(&"a", &"two", &txn).unwrap();
+        db.set(&"b", &&[0u8, 255][..], &txn).unwrap();
+
+        let mut out: Vec<u8> = Vec::new();
+        db.dump(&txn, &mut out).unwrap();
+        let dump = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines, vec![
+            "\"a\" => \"one\"",
+            "    => \"two\"",
+            "\"b\" => 0x00ff",
+        ]);
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_error_variants() {
+    assert!(matches!(MdbError::new_with_code(ffi::MDB_MAP_FULL), MdbError::MapFull(_)));
+    assert!(matches!(MdbError::new_with_code(ffi::MDB_DBS_FULL), MdbError::DbsFull));
+    assert!(matches!(MdbError::new_with_code(ffi::MDB_BAD_VALSIZE), MdbError::BadValSize));
+    assert!(matches!(MdbError::new_with_code(libc::EINVAL), MdbError::Other(_, _)));
+    assert_eq!(format!("{}", MdbError::ReadersFull), "max readers reached");
+
+    let env = EnvBuilder::new()
+        .max_dbs(1)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    env.create_db("first", DbFlags::empty()).unwrap();
+    match env.create_db("second", DbFlags::empty()) {
+        Err(MdbError::DbsFull) => (),
+        other => panic!("expected DbsFull, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_db_stat() {
+    let path = next_path();
+    {
+        let env = EnvBuilder::new().max_dbs(5).open(&path, USER_DIR).unwrap();
+        let db = env.create_db("stats", DbFlags::empty()).unwrap();
+        let txn = env.new_transaction().unwrap();
+        for i in 0..10u64 {
+            db.set(&i, &"value", &txn).unwrap();
+        }
+        txn.commit().unwrap();
+
+        let reader = env.get_reader().unwrap();
+        let stat = env.db_stat("stats", &reader).unwrap();
+        assert_eq!(stat.ms_entries, 10);
+        assert_eq!(stat.ms_depth, 1);
+        assert_eq!(stat.ms_leaf_pages, 1);
+        assert_eq!(stat.ms_overflow_pages, 0);
+    }
+
+    // db isn't cached after reopening
+    let env = EnvBuilder::new().max_dbs(5).open(&path, USER_DIR).unwrap();
+    let reader = env.get_reader().unwrap();
+    assert_eq!(env.db_stat("stats", &reader).unwrap().ms_entries, 10);
+    assert_eq!(env.db_stat("", &reader).unwrap().ms_entries, 1);
+    assert!(matches!(env.db_stat("missing", &reader), Err(MdbError::NotFound)));
+}
+
+#[test]
+fn test_load() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let src = env.create_db("src", database::DB_ALLOW_DUPS).unwrap();
+    let dst = env.create_db("dst", database::DB_ALLOW_DUPS).unwrap();
+    let from_tool = env.create_db("from_tool", DbFlags::empty()).unwrap();
+    let from_tool_print = env.create_db("from_tool_print", DbFlags::empty()).unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        src.set(&"a", &"one", &txn).unwrap();
+        src.set(&"a", &"two", &txn).unwrap();
+        src.set(&"quote\"d", &&[0u8, 1][..], &txn).unwrap();
+        src.set(&"with => arrow", &"x => y", &txn).unwrap();
+
+        let mut out: Vec<u8> = Vec::new();
+        src.dump(&txn, &mut out).unwrap();
+        assert_eq!(dst.load(&txn, &mut &out[..]).unwrap(), 4);
+        assert!(src.equals(&dst, &txn, &txn).unwrap());
+
+        // as written by `mdb_dump` and `mdb_dump -p`
+        let dump = "VERSION=3\nformat=bytevalue\ntype=btree\nHEADER=END\n 61\n 6f6e65\n 625c\n 780079\nDATA=END\n";
+        assert_eq!(from_tool.load(&txn, &mut dump.as_bytes()).unwrap(), 2);
+        let dump = "VERSION=3\nformat=print\ntype=btree\nHEADER=END\n a\n one\n b\\\\\n x\\00y\nDATA=END\n";
+        assert_eq!(from_tool_print.load(&txn, &mut dump.as_bytes()).unwrap(), 2);
+        assert!(from_tool.equals(&from_tool_print, &txn, &txn).unwrap());
+        assert_eq!(from_tool.get::<&[u8]>(&"b\\", &txn).unwrap(), b"x\0y");
+
+        assert!(dst.load(&txn, &mut "garbage".as_bytes()).is_err());
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_fragmentation() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_INT_KEY).unwrap();
+    let test_data: [u8; 0xFF] = [0x5A; 0xFF];
+
+    let txn = env.new_transaction().unwrap();
+    {
+        for k in 0..2000u64 {
+            db.set(&k, &(&test_data[..]), &txn).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    assert!(env.fragmentation().unwrap() < 0.1);
+
+    let txn = env.new_transaction().unwrap();
+    {
+        for k in 0..1800u64 {
+            db.del(&k, &txn).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let fragmentation = env.fragmentation().unwrap();
+    assert!(env.freelist_pages().unwrap() > 0);
+    assert!(fragmentation > 0.5, "fragmentation {} is too low", fragmentation);
+
+    let env = env.compact_in_place(USER_DIR).unwrap();
+    let compacted = env.fragmentation().unwrap();
+    assert!(compacted < fragmentation, "compacted {} >= {}", compacted, fragmentation);
+
+    let db = env.get_default_db(database::DB_INT_KEY).unwrap();
+    let reader = env.get_reader().unwrap();
+    assert_eq!(db.get::<&[u8]>(&1999u64, &reader).unwrap(), &test_data[..]);
+    assert!(db.get::<&[u8]>(&0u64, &reader).is_err());
+}
+
+#[test]
+fn test_reset_reader() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    {
+        let txn = env.new_transaction().unwrap();
+        db.set(&"key", &"old", &txn).unwrap();
+        txn.commit().unwrap();
+    }
+
+    let reader = env.get_reader().unwrap();
+    assert_eq!(db.get::<String>(&"key", &reader).unwrap(), "old");
+    let released = reader.into_reset();
+
+    {
+        let txn = env.new_transaction().unwrap();
+        db.set(&"key", &"new", &txn).unwrap();
+        txn.commit().unwrap();
+    }
+
+    let reader = released.renew().unwrap();
+    assert_eq!(db.get::<String>(&"key", &reader).unwrap(), "new");
+}
+
+#[test]
+fn test_iter_entries() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_INT_KEY).unwrap();
+    let txn = env.new_transaction().unwrap();
+    for i in 0..5u32 {
+        db.set(&i, &format!("value-{}", i), &txn).unwrap();
+    }
+
+    let entries: Vec<(u32, String)> = db.iter(&txn).unwrap().entries::<u32, String>().collect();
+    let expected: Vec<(u32, String)> = (0..5u32).map(|i| (i, format!("value-{}", i))).collect();
+    assert_eq!(entries, expected);
+
+    let keys: Vec<u32> = db.iter(&txn).unwrap().keys::<u32>().collect();
+    assert_eq!(keys, vec![0, 1, 2, 3, 4]);
+    let values: Vec<String> = db.iter(&txn).unwrap().values::<String>().rev().collect();
+    assert_eq!(values[0], "value-4");
+}
+
+#[test]
+fn test_value_len() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&"short", &"abc", &txn).unwrap();
+    db.set(&"long", &vec![0u8; 5000], &txn).unwrap();
+
+    assert_eq!(db.value_len(&"short", &txn).unwrap(), 3);
+    assert_eq!(db.value_len(&"long", &txn).unwrap(), 5000);
+    assert!(matches!(db.value_len(&"missing", &txn), Err(MdbError::NotFound)));
+}
+
+#[test]
+fn test_read_one() {
+    let path = next_path();
+    {
+        let env = EnvBuilder::new().max_dbs(1).open(&path, USER_DIR).unwrap();
+        let db = env.create_db("config", DbFlags::empty()).unwrap();
+        let txn = env.new_transaction().unwrap();
+        db.set(&"name", &"lmdb", &txn).unwrap();
+        txn.commit().unwrap();
+    }
+
+    let value: Option<String> = environment::read_one(&path, "config", &"name").unwrap();
+    assert_eq!(value, Some("lmdb".to_owned()));
+    let missing: Option<String> = environment::read_one(&path, "config", &"other").unwrap();
+    assert_eq!(missing, None);
+    assert!(environment::read_one::<_, String>(&path, "absent", &"name").is_err());
+}
+
+#[test]
+fn test_get_bytes() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    {
+        let txn = env.new_transaction().unwrap();
+        db.set(&"key", &"value", &txn).unwrap();
+        txn.commit().unwrap();
+    }
+
+    let reader = env.get_reader().unwrap();
+    let first = db.get_bytes(&"key", &reader).unwrap();
+    let second = db.get_bytes(&"key", &reader).unwrap();
+    assert_eq!(first, b"value");
+    // both point to the same place in memory map
+    assert_eq!(first.as_ptr(), second.as_ptr());
+    assert!(matches!(db.get_bytes(&"missing", &reader), Err(MdbError::NotFound)));
+}
+
+#[test]
+fn test_write_limit() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_INT_KEY).unwrap();
+    let value = [0u8; 100];
+
+    let mut txn = env.new_transaction().unwrap();
+    txn.set_write_limit(500);
+    let mut next = 0u64;
+    let err = loop {
+        match db.set(&next, &(&value[..]), &txn) {
+            Ok(()) => next += 1,
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(next, 4);
+    assert_eq!(txn.bytes_written(), 4 * 108);
+    assert!(matches!(err, MdbError::WriteLimit(_)));
+    txn.commit().unwrap();
+
+    // retrying in a new transaction succeeds
+    let mut txn = env.new_transaction().unwrap();
+    txn.set_write_limit(500);
+    db.set(&next, &(&value[..]), &txn).unwrap();
+    assert_eq!(txn.bytes_written(), 108);
+    txn.commit().unwrap();
+
+    let reader = env.get_reader().unwrap();
+    assert_eq!(db.stat(&reader).unwrap().ms_entries, 5);
+}
+
+#[test]
+fn test_write_limit_cursor() {
+    let env = EnvBuilder::new()
+        .max_dbs(5)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let db = env.create_db("fixed", database::DB_ALLOW_DUPS | database::DB_DUP_FIXED).unwrap();
+    let values: Vec<u32> = (0..10).collect();
+
+    let mut txn = env.new_transaction().unwrap();
+    txn.set_write_limit(55);
+    {
+        let mut cursor = db.new_cursor(&txn).unwrap();
+        cursor.set(&"key", &100u32, 0).unwrap();
+        cursor.move_to_key(&"key").unwrap();
+        assert_eq!(cursor.add_items(&values).unwrap(), 10);
+        cursor.move_to_key(&"key").unwrap();
+        assert!(matches!(cursor.add_items(&values[..3]), Err(MdbError::WriteLimit(_))));
+        assert!(matches!(cursor.add_item(&200u32), Err(MdbError::WriteLimit(_))));
+    }
+    assert_eq!(txn.bytes_written(), 7 + 43);
+    txn.commit().unwrap();
+
+    let reader = env.get_reader().unwrap();
+    assert_eq!(db.item_iter(&"key", &reader).unwrap().count(), 11);
+}
+
+#[test]
+fn test_item_iter_len() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_ALLOW_DUPS).unwrap();
+    let txn = env.new_transaction().unwrap();
+    for value in &["a", "b", "c"] {
+        db.set(&"key", value, &txn).unwrap();
+    }
+    db.set(&"other", &"x", &txn).unwrap();
+
+    let mut iter = db.item_iter(&"key", &txn).unwrap();
+    assert_eq!(iter.len(), 3);
+    iter.next().unwrap();
+    assert_eq!(iter.len(), 2);
+    let rest: Vec<String> = iter.values::<String>().collect();
+    assert_eq!(rest, vec!["b", "c"]);
+
+    let mut iter = db.item_iter(&"key", &txn).unwrap();
+    let mut values = Vec::with_capacity(iter.len());
+    for cv in &mut iter {
+        values.push(cv.get_value::<String>());
+    }
+    assert_eq!(values.len(), values.capacity());
+    assert_eq!(iter.len(), 0);
+
+    assert_eq!(db.item_iter(&"missing", &txn).unwrap().len(), 0);
+}
+
+#[test]
+fn test_item_iter_rev_len() {
+    let env = EnvBuilder::new().max_dbs(2).open(&next_path(), USER_DIR).unwrap();
+    let db = env.create_db("dups", database::DB_ALLOW_DUPS).unwrap();
+    let plain = env.create_db("plain", DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    for value in &["a", "b", "c", "d", "e"] {
+        db.set(&"key", value, &txn).unwrap();
+        db.set(&"other", value, &txn).unwrap();
+        plain.set(value, value, &txn).unwrap();
+    }
+
+    let rev: Vec<String> = db.item_iter(&"key", &txn).unwrap().values::<String>().rev().collect();
+    assert_eq!(rev, vec!["e", "d", "c", "b", "a"]);
+
+    let mut iter = db.item_iter(&"key", &txn).unwrap();
+    assert_eq!(iter.len(), 5);
+    assert_eq!(iter.next().unwrap().get_value::<&str>(), "a");
+    assert_eq!(iter.len(), 4);
+    assert_eq!(iter.next_back().unwrap().get_value::<&str>(), "e");
+    assert_eq!(iter.len(), 3);
+    assert_eq!(iter.next_back().unwrap().get_value::<&str>(), "d");
+    assert_eq!(iter.len(), 2);
+    let rest: Vec<String> = iter.values::<String>().collect();
+    assert_eq!(rest, vec!["b", "c"]);
+
+    // only item of the key, never steps to a neighbour
+    let mut iter = plain.item_iter(&"c", &txn).unwrap();
+    assert_eq!(iter.len(), 1);
+    assert_eq!(iter.next_back().unwrap().get_value::<&str>(), "c");
+    assert_eq!(iter.len(), 0);
+    assert!(iter.next_back().is_none());
+    assert!(iter.next().is_none());
+    assert_eq!(plain.item_iter(&"c", &txn).unwrap().count(), 1);
+}
+
+#[test]
+fn test_pop() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_ALLOW_DUPS).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&"job", &"first", &txn).unwrap();
+    db.set(&"job", &"second", &txn).unwrap();
+    db.set(&"other", &"value", &txn).unwrap();
+
+    assert_eq!(db.pop::<String>(&"other", &txn).unwrap(), "value");
+    assert!(matches!(db.pop::<String>(&"other", &txn), Err(MdbError::NotFound)));
+
+    // only first item is removed for dup key
+    assert_eq!(db.pop::<String>(&"job", &txn).unwrap(), "first");
+    assert_eq!(db.get::<String>(&"job", &txn).unwrap(), "second");
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_with_value() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&"owner", &"alice", &txn).unwrap();
+
+    let granted = db.with_value(&"owner", &txn, |owner: Option<String>| {
+        if owner.as_deref() == Some("alice") {
+            db.set(&"grant", &"alice", &txn)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }).unwrap();
+    assert!(granted);
+    assert_eq!(db.get::<String>(&"grant", &txn).unwrap(), "alice");
+
+    let missing = db.with_value(&"nobody", &txn, |value: Option<String>| Ok(value.is_none())).unwrap();
+    assert!(missing);
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_iter_default_data() {
+    let env = EnvBuilder::new().max_dbs(2).open(&next_path(), USER_DIR).unwrap();
+    let named = env.create_db("named", DbFlags::empty()).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    named.set(&"inner", &"value", &txn).unwrap();
+    db.set(&"alpha", &"1", &txn).unwrap();
+    db.set(&"zeta", &"2", &txn).unwrap();
+    txn.commit().unwrap();
+
+    let keys: Vec<Vec<u8>> = env.iter_default_data().unwrap().map(|(k, _)| k).collect();
+    assert_eq!(keys, vec![b"alpha".to_vec(), b"zeta".to_vec()]);
+
+    // ~ keys with nul bytes are plain data, probes don't take db slots
+    let txn = env.new_transaction().unwrap();
+    db.set(&"nul\0key", &"3", &txn).unwrap();
+    txn.commit().unwrap();
+    for _ in 0..3 {
+        let keys: Vec<Vec<u8>> = env.iter_default_data().unwrap().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![b"alpha".to_vec(), b"nul\0key".to_vec(), b"zeta".to_vec()]);
+    }
+    env.create_db("other", DbFlags::empty()).unwrap();
+}
+
+#[test]
+fn test_nested_write_txn_abort() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let parent = env.new_transaction().unwrap();
+    db.set(&"parent", &"value", &parent).unwrap();
+    {
+        let child = parent.new_child().unwrap();
+        db.set(&"child", &"value", &child).unwrap();
+        assert_eq!(db.get::<String>(&"parent", &child).unwrap(), "value");
+        child.abort();
+    }
+    assert!(matches!(db.get::<String>(&"child", &parent), Err(MdbError::NotFound)));
+    assert_eq!(db.get::<String>(&"parent", &parent).unwrap(), "value");
+    parent.commit().unwrap();
+}
+
+#[test]
+fn test_child_of_readonly_txn() {
+    use crate::transaction::Transaction;
+
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    // read-only Transaction is built from a native handle, public API
+    // never returns one
+    let mut handle: *mut ffi::MDB_txn = ::std::ptr::null_mut();
+    {
+        let reader = env.get_reader().unwrap();
+        unsafe {
+            let env_handle = ffi::mdb_txn_env(reader.get_handle());
+            drop(reader);
+            assert_eq!(ffi::mdb_txn_begin(env_handle, ::std::ptr::null_mut(), ffi::MDB_RDONLY, &mut handle), 0);
+        }
+    }
+    let txn = Transaction::new_with_native(NativeTransaction::new_with_handle(handle, ffi::MDB_RDONLY as usize, &env));
+
+    let mut results = vec![txn.new_child().map(|_| ()), txn.new_ro_child().map(|_| ())];
+    drop(txn);
+    results.push(env.get_reader().unwrap().new_ro_child().map(|_| ()));
+    for res in results {
+        match res {
+            Err(MdbError::StateError(msg)) => assert!(msg.contains("read-only"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_snapshot_token() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let token = env.snapshot_token().unwrap();
+    {
+        let reader = env.get_reader().unwrap();
+        let _ = db.get::<String>(&"key", &reader);
+    }
+    assert_eq!(env.snapshot_token().unwrap(), token);
+
+    let txn = env.new_transaction().unwrap();
+    db.set(&"key", &"value", &txn).unwrap();
+    txn.commit().unwrap();
+    assert_eq!(env.snapshot_token().unwrap(), token + 1);
+}
+
+#[test]
+fn test_update() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_INT_KEY).unwrap();
+    let txn = env.new_transaction().unwrap();
+    let key = 1u64;
+
+    for _ in 0..3 {
+        db.update(&key, &txn, |count: Option<u64>| Some(count.unwrap_or(0) + 1)).unwrap();
+    }
+    assert_eq!(db.get::<u64>(&key, &txn).unwrap(), 3);
+
+    db.update(&key, &txn, |_: Option<u64>| None).unwrap();
+    assert!(matches!(db.get::<u64>(&key, &txn), Err(MdbError::NotFound)));
+    // deleting a missing key is a no-op
+    db.update(&key, &txn, |_: Option<u64>| None).unwrap();
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_from_pod() {
+    let value = 0x0102_0304_0506_0708u64;
+    let mdb_value = MdbValue::from_pod(&value);
+    assert_eq!(mdb_value.get_size(), 8);
+    assert_eq!(u64::from_mdb_value(&mdb_value), value);
+
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_INT_KEY).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&value, &value, &txn).unwrap();
+    assert_eq!(db.get::<u64>(&value, &txn).unwrap(), value);
+}
+
+#[test]
+fn test_int_compare() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set_int_compare(IntWidth::I64Native, &txn).unwrap();
+    for i in &[3i64, -7, 0, 100, -1] {
+        db.set(i, &0u8, &txn).unwrap();
+    }
+    let keys: Vec<i64> = db.iter(&txn).unwrap().keys::<i64>().collect();
+    assert_eq!(keys, vec![-7, -1, 0, 3, 100]);
+    txn.commit().unwrap();
+
+    let (a, b) = (1u64.to_be_bytes(), 256u64.to_be_bytes());
+    let (a, b) = (&a[..], &b[..]);
+    let (va, vb) = (a.to_mdb_value(), b.to_mdb_value());
+    assert_eq!(compare::cmp_u64_be(&va.value, &vb.value), -1);
+    assert_eq!(compare::cmp_u64_be(&vb.value, &va.value), 1);
+    let (a, b) = (&a[4..], &b[4..]);
+    let (va, vb) = (a.to_mdb_value(), b.to_mdb_value());
+    assert_eq!(compare::cmp_u32_be(&va.value, &va.value), 0);
+    assert_eq!(compare::cmp_u32_be(&va.value, &vb.value), -1);
+}
+
+#[test]
+fn test_max_key_size() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let max = env.max_key_size().unwrap();
+    assert!(max > 0);
+    assert_eq!(env.get_maxkeysize() as usize, max);
+
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&vec![1u8; max], &"fits", &txn).unwrap();
+    assert!(matches!(db.set(&vec![1u8; max + 1], &"too long", &txn), Err(MdbError::BadValSize)));
+}
+
+#[test]
+fn test_union_into() {
+    let env = EnvBuilder::new().max_dbs(3).open(&next_path(), USER_DIR).unwrap();
+    let left = env.create_db("left", database::DB_ALLOW_DUPS).unwrap();
+    let right = env.create_db("right", database::DB_ALLOW_DUPS).unwrap();
+    let plain = env.create_db("plain", DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    for (k, v) in &[("a", "1"), ("a", "2"), ("b", "1")] {
+        left.set(k, v, &txn).unwrap();
+    }
+    for (k, v) in &[("a", "2"), ("a", "3"), ("c", "1")] {
+        right.set(k, v, &txn).unwrap();
+    }
+
+    assert_eq!(left.union_into(&right, &txn).unwrap(), 2);
+    let a: Vec<String> = left.item_iter(&"a", &txn).unwrap().values::<String>().collect();
+    assert_eq!(a, vec!["1", "2", "3"]);
+    assert_eq!(left.keys::<String>(&txn).unwrap(), vec!["a", "b", "c"]);
+    assert!(left.union_into(&plain, &txn).is_err());
+}
+
+#[test]
+fn test_upgrade() {
+    let path = next_path();
+    {
+        let env = EnvBuilder::new().open(&path, USER_DIR).unwrap();
+        let db = env.get_default_db(DbFlags::empty()).unwrap();
+
+        let reader = env.get_reader().unwrap();
+        assert!(matches!(db.get::<String>(&"key", &reader), Err(MdbError::NotFound)));
+        let txn = reader.upgrade().unwrap();
+        db.set(&"key", &"value", &txn).unwrap();
+        txn.commit().unwrap();
+
+        let reader = env.get_reader().unwrap();
+        assert_eq!(db.get::<String>(&"key", &reader).unwrap(), "value");
+    }
+
+    let ro_env = EnvBuilder::new().flags(environment::ENV_CREATE_READONLY).open(&path, USER_DIR).unwrap();
+    assert!(matches!(ro_env.get_reader().unwrap().upgrade(), Err(MdbError::StateError(_))));
+}
+
+#[test]
+fn test_stat_info_display() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&"key", &"value", &txn).unwrap();
+    txn.commit().unwrap();
+
+    let stat = env.stat_pretty().unwrap().to_string();
+    assert!(stat.contains("page size: "), "{}", stat);
+    assert!(stat.contains("entries: 1"), "{}", stat);
+
+    let info = env.info_pretty().unwrap();
+    let text = info.to_string();
+    assert!(text.contains(&format!("map size: {}", info.0.me_mapsize)), "{}", text);
+    assert!(text.contains("last txnid: 1"), "{}", text);
+    assert!(format!("{:?}", info).starts_with("EnvInfo {"));
+}
+
+#[test]
+fn test_drain() {
+    let env = EnvBuilder::new().max_dbs(2).open(&next_path(), USER_DIR).unwrap();
+    let plain = env.create_db("plain", DbFlags::empty()).unwrap();
+    let dups = env.create_db("dups", database::DB_ALLOW_DUPS).unwrap();
+    let txn = env.new_transaction().unwrap();
+    for (k, v) in &[("a", "1"), ("b", "2"), ("c", "3")] {
+        plain.set(k, v, &txn).unwrap();
+    }
+    for (k, v) in &[("a", "1"), ("a", "2"), ("b", "1"), ("b", "2"), ("c", "1")] {
+        dups.set(k, v, &txn).unwrap();
+    }
+
+    // stop in the middle, the rest stays
+    let first: Vec<(Vec<u8>, Vec<u8>)> = plain.drain(&txn).unwrap().take(2).map(|r| r.unwrap()).collect();
+    assert_eq!(first, vec![(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), b"2".to_vec())]);
+    assert_eq!(plain.keys::<String>(&txn).unwrap(), vec!["c"]);
+
+    let drained: Vec<(Vec<u8>, Vec<u8>)> = dups.drain(&txn).unwrap().map(|r| r.unwrap()).collect();
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = [("a", "1"), ("a", "2"), ("b", "1"), ("b", "2"), ("c", "1")].iter()
+        .map(|&(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()))
+        .collect();
+    assert_eq!(drained, expected);
+    assert_eq!(dups.stat(&txn).unwrap().ms_entries, 0);
+    txn.commit().unwrap();
+
+    let reader = env.get_reader().unwrap();
+    assert!(matches!(plain.drain(&reader), Err(MdbError::StateError(_))));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_resident_pages() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_INT_KEY).unwrap();
+    let txn = env.new_transaction().unwrap();
+    for i in 0..1000u64 {
+        db.set(&i, &i, &txn).unwrap();
+    }
+    txn.commit().unwrap();
+
+    let reader = env.get_reader().unwrap();
+    for i in 0..1000u64 {
+        assert_eq!(db.get::<u64>(&i, &reader).unwrap(), i);
+    }
+
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let resident = env.resident_pages().unwrap();
+    assert!(resident > 0);
+    assert!(resident <= env.info().unwrap().me_mapsize / page_size);
+}
+
+#[test]
+fn test_page_size() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let os_page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    assert_eq!(env.page_size().unwrap(), os_page_size);
+}
+
+#[test]
+fn test_checked_int_conversions() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&"small", &7u32, &txn).unwrap();
+
+    let bytes = db.get_bytes(&"small", &txn).unwrap();
+    let value = bytes.to_mdb_value();
+    assert_eq!(value.as_u32().unwrap(), 7);
+    assert!(matches!(value.as_u64(), Err(MdbError::Conversion(_))));
+    assert!(matches!(value.as_i16(), Err(MdbError::Conversion(_))));
+}
+
+#[test]
+fn test_named_db_names() {
+    let env = EnvBuilder::new().max_dbs(5).open(&next_path(), USER_DIR).unwrap();
+    for name in &["users", "orders", "audit"] {
+        env.create_db(name, DbFlags::empty()).unwrap();
+    }
+
+    let reader = env.get_reader().unwrap();
+    assert_eq!(env.named_db_count(&reader).unwrap(), 3);
+    assert_eq!(env.named_db_names(&reader).unwrap(), vec!["audit", "orders", "users"]);
+    drop(reader);
+
+    // ~ must not start another write txn while one is held
+    let txn = env.new_transaction().unwrap();
+    assert_eq!(env.named_db_count(&txn).unwrap(), 3);
+    assert_eq!(env.named_db_names(&txn).unwrap(), vec!["audit", "orders", "users"]);
+    txn.abort();
+}
+
+#[test]
+fn test_dry_run() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+
+    let written = env.dry_run(|txn| {
+        db.set(&"a", &"1", txn)?;
+        db.set(&"b", &"2", txn)?;
+        Ok(db.stat(txn)?.ms_entries)
+    }).unwrap();
+    assert_eq!(written, 2);
+
+    let failed = env.dry_run(|txn| db.insert(&"a", &"1", txn).and_then(|_| db.insert(&"a", &"2", txn)));
+    assert!(matches!(failed, Err(MdbError::KeyExists)));
+
+    let reader = env.get_reader().unwrap();
+    assert_eq!(db.stat(&reader).unwrap().ms_entries, 0);
+}
+
+#[test]
+fn test_cursor_is_positioned() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&"key", &"value", &txn).unwrap();
+
+    let mut cursor = db.new_cursor(&txn).unwrap();
+    assert!(!cursor.is_positioned());
+    cursor.move_to_first().unwrap();
+    assert!(cursor.is_positioned());
+    assert!(cursor.move_to_key(&"missing").is_err());
+    assert!(!cursor.is_positioned());
+}
+
+#[test]
+fn test_replace_value() {
+    let env = EnvBuilder::new().max_dbs(1).open(&next_path(), USER_DIR).unwrap();
+    let dups = env.create_db("dups", database::DB_ALLOW_DUPS).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+
+    assert_eq!(db.replace_value::<String>(&"key", &"first", &txn).unwrap(), None);
+    assert_eq!(db.replace_value::<String>(&"key", &"second", &txn).unwrap(), Some("first".to_owned()));
+    assert_eq!(db.get::<String>(&"key", &txn).unwrap(), "second");
+    assert!(matches!(dups.replace_value::<String>(&"key", &"value", &txn), Err(MdbError::StateError(_))));
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_bytes_values() {
+    use bytes::Bytes;
+
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    let value = Bytes::from_static(b"payload");
+    db.set(&"key", &value, &txn).unwrap();
+    db.set(&Bytes::from_static(b"other"), &&value, &txn).unwrap();
+    txn.commit().unwrap();
+
+    let reader = env.get_reader().unwrap();
+    assert_eq!(db.get::<Bytes>(&"key", &reader).unwrap(), value);
+    assert_eq!(db.get::<Bytes>(&"other", &reader).unwrap(), value);
+}
+
+#[test]
+fn test_last_txnid() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let before = env.last_txnid().unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    db.set(&"key", &"value", &txn).unwrap();
+    txn.commit().unwrap();
+    assert!(env.last_txnid().unwrap() > before);
+
+    let _reader = env.get_reader().unwrap();
+    assert!(env.num_readers().unwrap() >= 1);
+}
+
+#[test]
+fn test_item_sizes() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_ALLOW_DUPS).unwrap();
+    let txn = env.new_transaction().unwrap();
+    for value in &["bbb", "ccccccc", "a"] {
+        db.set(&"key", value, &txn).unwrap();
+    }
+    db.set(&"other", &"value", &txn).unwrap();
+
+    assert_eq!(db.item_sizes(&"key", &txn).unwrap(), vec![1, 3, 7]);
+    assert_eq!(db.item_sizes(&"missing", &txn).unwrap(), Vec::<usize>::new());
+}
+
+#[test]
+fn test_transact() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+
+    let count = env.transact(|txn| {
+        db.set(&"a", &"1", txn)?;
+        Ok(db.stat(txn)?.ms_entries)
+    }).unwrap();
+    assert_eq!(count, 1);
+
+    let failed = env.transact(|txn| {
+        db.set(&"b", &"2", txn)?;
+        db.insert(&"a", &"again", txn)
+    });
+    assert!(matches!(failed, Err(MdbError::KeyExists)));
+
+    let reader = env.get_reader().unwrap();
+    assert_eq!(db.get::<String>(&"a", &reader).unwrap(), "1");
+    assert!(matches!(db.get::<String>(&"b", &reader), Err(MdbError::NotFound)));
+}
+
+#[test]
+fn test_with_retrying_write_txn() {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 };
+    let path = next_path();
+    let env = EnvBuilder::new().map_size(64 * page_size).open(&path, USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+
+    // ~ another environment grows the map and fills it past the size
+    // known to env, so env's next write txn fails with MapResized.
+    // It stays open until env is closed: file locks are per process,
+    // so closing it would destroy LMDB mutexes still used by env
+    let other = EnvBuilder::new().map_size(1024 * page_size).open(&path, USER_DIR).unwrap();
+    {
+        let other_db = other.get_default_db(DbFlags::empty()).unwrap();
+        let txn = other.new_transaction().unwrap();
+        let value = vec![0u8; page_size as usize];
+        for i in 0..128u64 {
+            other_db.set(&i, &value, &txn).unwrap();
+        }
+        txn.commit().unwrap();
+    }
+    assert!(matches!(env.new_transaction(), Err(MdbError::MapResized)));
+
+    let attempts = AtomicUsize::new(0);
+    let res = env.with_retrying_write_txn(2, |txn| {
+        attempts.fetch_add(1, Ordering::SeqCst);
+        db.set(&"key", &"value", txn)
+    });
+    assert!(res.is_ok());
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    assert_eq!(env.info().unwrap().me_mapsize as u64, 1024 * page_size);
+
+    let failed = env.with_retrying_write_txn(1, |_| Err::<(), _>(MdbError::BadTxn));
+    assert!(matches!(failed, Err(MdbError::BadTxn)));
+
+    let reader = env.get_reader().unwrap();
+    assert_eq!(db.get::<String>(&"key", &reader).unwrap(), "value");
+    assert_eq!(db.stat(&reader).unwrap().ms_entries, 129);
+    drop(reader);
+    drop(env);
+    drop(other);
+}
+
+#[test]
+fn test_remove() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&"key", &"value", &txn).unwrap();
+
+    assert!(db.remove(&"key", &txn).unwrap());
+    assert!(!db.remove(&"key", &txn).unwrap());
+    assert!(!db.remove(&"absent", &txn).unwrap());
+}
+
+#[test]
+fn test_compiled_max_key_size() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    assert_eq!(environment::compiled_max_key_size().unwrap(), env.max_key_size().unwrap());
+}
+
+#[test]
+fn test_write_map_nesting() {
+    let env = EnvBuilder::new()
+        .flags(environment::ENV_CREATE_WRITE_MAP)
+        .open(&next_path(), USER_DIR)
+        .unwrap();
+    let txn = env.new_transaction().unwrap();
+    let res = txn.new_child().map(|_| ());
+    match res {
+        Err(MdbError::StateError(msg)) => assert!(msg.contains("ENV_CREATE_WRITE_MAP"), "{}", msg),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn test_cursor_key_first_last_item() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_ALLOW_DUPS | database::DB_ALLOW_INT_DUPS).unwrap();
+    let txn = env.new_transaction().unwrap();
+    for value in &[30u32, 10, 20] {
+        db.set(&"a", value, &txn).unwrap();
+    }
+    for value in &[5u32, 500] {
+        db.set(&"b", value, &txn).unwrap();
+    }
+
+    let mut cursor = db.new_cursor(&txn).unwrap();
+    cursor.move_to_key_last_item(&"a").unwrap();
+    assert_eq!(cursor.get_value::<u32>().unwrap(), 30);
+    cursor.move_to_key_first_item(&"a").unwrap();
+    assert_eq!(cursor.get_value::<u32>().unwrap(), 10);
+    cursor.move_to_key_last_item(&"b").unwrap();
+    assert_eq!(cursor.get_value::<u32>().unwrap(), 500);
+    assert!(matches!(cursor.move_to_key_first_item(&"c"), Err(MdbError::NotFound)));
+    assert!(matches!(cursor.move_to_key_last_item(&"c"), Err(MdbError::NotFound)));
+}
+
+#[test]
+fn test_filter_map_values() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(database::DB_INT_KEY).unwrap();
+    let txn = env.new_transaction().unwrap();
+    for i in 0..10u64 {
+        db.set(&i, &i, &txn).unwrap();
+    }
+
+    let doubled: Vec<u64> = db.iter(&txn).unwrap()
+        .filter_map_values(|v: u64| if v.is_multiple_of(2) { Some(v * 2) } else { None })
+        .collect();
+    assert_eq!(doubled, vec![0, 4, 8, 12, 16]);
+}
+
+#[test]
+fn test_copy_to_path_create() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&"key", &"value", &txn).unwrap();
+    txn.commit().unwrap();
+
+    let dest = next_path().join("nested").join("backup");
+    assert!(env.copy_to_path(&dest).is_err());
+    env.copy_to_path_create(&dest).unwrap();
+
+    let copy = EnvBuilder::new().autocreate_dir(false).open(&dest, USER_DIR).unwrap();
+    let db = copy.get_default_db(DbFlags::empty()).unwrap();
+    let reader = copy.get_reader().unwrap();
+    assert_eq!(db.get::<String>(&"key", &reader).unwrap(), "value");
+}
+
+#[test]
+fn test_copy_to_env() {
+    let env = EnvBuilder::new().max_dbs(2).open(&next_path(), USER_DIR).unwrap();
+    let users = env.create_db("users", database::DB_ALLOW_DUPS).unwrap();
+    let orders = env.create_db("orders", DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    for (k, v) in &[("alice", "admin"), ("alice", "dev"), ("bob", "dev")] {
+        users.set(k, v, &txn).unwrap();
+    }
+    orders.set(&"order", &"1", &txn).unwrap();
+    txn.commit().unwrap();
+
+    let dest = EnvBuilder::new().max_dbs(2).open(&next_path(), USER_DIR).unwrap();
+    let reader = env.get_reader().unwrap();
+    assert_eq!(users.copy_to_env(&dest, "exported", &reader).unwrap(), 3);
+
+    let dest_reader = dest.get_reader().unwrap();
+    assert_eq!(dest.named_db_names(&dest_reader).unwrap(), vec!["exported"]);
+    let exported = dest.open_existing_db("exported", DbFlags::empty()).unwrap();
+    assert!(exported.get_flags(&dest_reader).unwrap().contains(database::DB_ALLOW_DUPS));
+    let roles: Vec<String> = exported.item_iter(&"alice", &dest_reader).unwrap().values::<String>().collect();
+    assert_eq!(roles, vec!["admin", "dev"]);
+    assert_eq!(exported.stat(&dest_reader).unwrap().ms_entries, 3);
+}
+
+#[test]
+fn test_fixed_size_array_key() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+
+    let uuid: [u8; 16] = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 1, 2, 3, 4, 5, 6, 7, 8];
+    let txn = env.new_transaction().unwrap();
+    {
+        db.set(&uuid, &"user", &txn).unwrap();
+        db.set(&"short", &[7u8; 4], &txn).unwrap();
+        assert_eq!("user", db.get::<&str>(&uuid, &txn).unwrap());
+        assert_eq!([7u8; 4], db.get::<[u8; 4]>(&"short", &txn).unwrap());
+
+        let keys: Vec<[u8; 16]> = db.iter(&txn).unwrap()
+            .filter(|cv| cv.get_key::<&[u8]>().len() == 16)
+            .map(|cv| cv.get_key::<[u8; 16]>())
+            .collect();
+        assert_eq!(keys, vec![uuid]);
+
+        assert!(matches!(db.try_get::<[u8; 16]>(&"short", &txn), Err(MdbError::Conversion(_))));
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_cursor_renew() {
+    // two readers in one thread
+    let env = EnvBuilder::new().flags(environment::ENV_CREATE_NO_TLS).open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&"key", &"old", &txn).unwrap();
+    txn.commit().unwrap();
+
+    let reader = env.get_reader().unwrap();
+    let mut recycled = env.get_reader().unwrap();
+    recycled.reset();
+
+    let mut cursor = db.new_cursor(&reader).unwrap();
+    cursor.move_to_key(&"key").unwrap();
+    assert_eq!("old", cursor.get_value::<&str>().unwrap());
+
+    let txn = env.new_transaction().unwrap();
+    db.set(&"key", &"new", &txn).unwrap();
+    txn.commit().unwrap();
+
+    recycled.renew().unwrap();
+    cursor.renew(&recycled).unwrap();
+    assert!(!cursor.is_positioned());
+    cursor.move_to_key(&"key").unwrap();
+    assert_eq!("new", cursor.get_value::<&str>().unwrap());
+}
+
+#[test]
+fn test_prefix_iter() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        for key in &["order:1", "user:", "user:alice", "user:bob", "users", "zone"] {
+            db.set(key, &"", &txn).unwrap();
+        }
+
+        let keys = |prefix: &[u8]| -> Vec<String> {
+            db.prefix_iter(prefix, &txn).unwrap().keys::<String>().collect()
+        };
+        assert_eq!(keys(b"user:"), vec!["user:", "user:alice", "user:bob"]);
+        assert_eq!(keys(b"user"), vec!["user:", "user:alice", "user:bob", "users"]);
+        assert_eq!(keys(b"").len(), 6);
+        assert!(keys(b"user:alice:settings").is_empty());
+        assert!(keys(b"zzz").is_empty());
+
+        let rev_keys = |prefix: &[u8]| -> Vec<String> {
+            db.prefix_iter(prefix, &txn).unwrap().keys::<String>().rev().collect()
+        };
+        assert_eq!(rev_keys(b"user:"), vec!["user:bob", "user:alice", "user:"]);
+        assert_eq!(rev_keys(b"user"), vec!["users", "user:bob", "user:alice", "user:"]);
+        assert_eq!(rev_keys(b"zone"), vec!["zone"]);
+        assert_eq!(rev_keys(b"").first().unwrap(), "zone");
+        assert!(rev_keys(b"user:alice:settings").is_empty());
+
+        let mut iter = db.prefix_iter(b"user", &txn).unwrap();
+        assert_eq!(iter.next_back().unwrap().get_key::<&str>(), "users");
+        assert_eq!(iter.next().unwrap().get_key::<&str>(), "user:");
+        assert_eq!(iter.count(), 2);
+    }
+    txn.commit().unwrap();
+
+    // prefix ending with 0xff has to carry into previous byte
+    let txn = env.new_transaction().unwrap();
+    {
+        for key in &[&[0x01u8][..], &[0x01, 0xff], &[0x01, 0xff, 0x00], &[0x01, 0xff, 0xff], &[0x02]] {
+            db.set(key, &"", &txn).unwrap();
+        }
+        let rev: Vec<Vec<u8>> = db.prefix_iter(&[0x01, 0xff], &txn).unwrap().keys::<Vec<u8>>().rev().collect();
+        assert_eq!(rev, vec![vec![0x01, 0xff, 0xff], vec![0x01, 0xff, 0x00], vec![0x01, 0xff]]);
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_txn_id() {
+    let env = EnvBuilder::new().flags(environment::ENV_CREATE_NO_TLS).open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+
+    let before = env.get_reader().unwrap();
+    let txn = env.new_transaction().unwrap();
+    assert_eq!(before.id() + 1, txn.id());
+    db.set(&"key", &"value", &txn).unwrap();
+    txn.commit().unwrap();
+
+    let after = env.get_reader().unwrap();
+    assert!(after.id() > before.id());
+    assert_eq!(after.id(), env.last_txnid().unwrap());
+    assert!(before.id() < env.last_txnid().unwrap());
+}
+
+#[test]
+fn test_oldest_reader_txnid() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    assert_eq!(env.oldest_reader_txnid().unwrap(), None);
+
+    let txn = env.new_transaction().unwrap();
+    db.set(&"key", &0u32, &txn).unwrap();
+    txn.commit().unwrap();
+
+    let reader = env.get_reader().unwrap();
+    let snapshot = reader.id();
+    for i in 1..5u32 {
+        let txn = env.new_transaction().unwrap();
+        db.set(&"key", &i, &txn).unwrap();
+        txn.commit().unwrap();
+    }
+
+    assert_eq!(env.oldest_reader_txnid().unwrap(), Some(snapshot));
+    assert_eq!(env.last_txnid().unwrap(), snapshot + 4);
+}
+
+#[test]
+fn test_put_if_newer() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    // one byte of kind followed by version and payload
+    let record = |version: u64, payload: &[u8]| -> Vec<u8> {
+        let mut v = vec![b'r'];
+        v.extend_from_slice(&version.to_be_bytes());
+        v.extend_from_slice(payload);
+        v
+    };
+
+    let txn = env.new_transaction().unwrap();
+    {
+        assert!(db.put_if_newer(&"doc", &record(1, b"first"), 1, &txn).unwrap());
+        assert!(!db.put_if_newer(&"doc", &record(1, b"same version"), 1, &txn).unwrap());
+        assert_eq!(record(1, b"first"), db.get::<Vec<u8>>(&"doc", &txn).unwrap());
+
+        assert!(db.put_if_newer(&"doc", &record(2, b"second"), 1, &txn).unwrap());
+        assert!(!db.put_if_newer(&"doc", &record(1, b"late"), 1, &txn).unwrap());
+        let stored = db.get::<Vec<u8>>(&"doc", &txn).unwrap();
+        assert_eq!(&stored[1..9], &2u64.to_be_bytes());
+        assert_eq!(&stored[9..], b"second");
+
+        assert!(matches!(db.put_if_newer(&"doc", b"short", 1, &txn), Err(MdbError::Conversion(_))));
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_scan() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    for i in 0..5u32 {
+        db.set(&i.to_be_bytes().to_vec(), &i, &txn).unwrap();
+    }
+    txn.commit().unwrap();
+
+    let mut reader = env.get_reader().unwrap();
+    let mut sum = 0;
+    for cv in db.scan(&reader) {
+        sum += cv.get_value::<u32>();
+    }
+    assert_eq!(sum, 10);
+    assert_eq!(db.try_scan(&reader).unwrap().count(), 5);
+
+    reader.reset();
+    assert!(db.try_scan(&reader).is_err());
+}
+
+#[test]
+#[should_panic(expected = "failed to open cursor for scan")]
+fn test_scan_panics_on_invalid_txn() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let mut reader = env.get_reader().unwrap();
+    reader.reset();
+    for _ in db.scan(&reader) {}
+}
+
+#[test]
+fn test_to_grouped_map() {
+    let env = EnvBuilder::new().max_dbs(1).open(&next_path(), USER_DIR).unwrap();
+    let db = env.create_db("roles", database::DB_ALLOW_DUPS).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        for (user, role) in &[("bob", "dev"), ("alice", "ops"), ("alice", "admin"), ("bob", "dev"), ("carol", "qa")] {
+            db.set(user, role, &txn).unwrap();
+        }
+
+        let map = db.to_grouped_map::<String, String>(&txn).unwrap();
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec!["alice", "bob", "carol"]);
+        assert_eq!(map["alice"], vec!["admin", "ops"]);
+        assert_eq!(map["bob"], vec!["dev"]);
+        assert_eq!(map["carol"], vec!["qa"]);
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_recreate() {
+    let env = EnvBuilder::new().max_dbs(2).open(&next_path(), USER_DIR).unwrap();
+    let db = env.create_db("items", DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&"key", &"old", &txn).unwrap();
+    txn.commit().unwrap();
+
+    let txn = env.new_transaction().unwrap();
+    {
+        let db = db.recreate(database::DB_ALLOW_DUPS, &txn).unwrap();
+        assert!(matches!(db.get::<&str>(&"key", &txn), Err(MdbError::NotFound)));
+        db.set(&"key", &"a", &txn).unwrap();
+        db.set(&"key", &"b", &txn).unwrap();
+    }
+    txn.commit().unwrap();
+
+    let db = env.get_db("items", DbFlags::empty()).unwrap();
+    {
+        let reader = env.get_reader().unwrap();
+        assert!(db.get_flags(&reader).unwrap().contains(database::DB_ALLOW_DUPS));
+        assert_eq!(db.item_sizes(&"key", &reader).unwrap().len(), 2);
+    }
+
+    // ~ aborted recreate leaves db as it was, reopened by name
+    let txn = env.new_transaction().unwrap();
+    db.recreate(DbFlags::empty(), &txn).unwrap();
+    txn.abort();
+    let db = env.get_db("items", DbFlags::empty()).unwrap();
+    let reader = env.get_reader().unwrap();
+    assert!(db.get_flags(&reader).unwrap().contains(database::DB_ALLOW_DUPS));
+    assert_eq!(db.item_sizes(&"key", &reader).unwrap().len(), 2);
+    drop(reader);
+
+    let default_db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    assert!(matches!(default_db.recreate(DbFlags::empty(), &txn), Err(MdbError::StateError(_))));
+}
+
+#[test]
+fn test_cursor_current_sizes() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        db.set(&"a", &vec![0u8; 100], &txn).unwrap();
+        db.set(&"bcd", &42u32, &txn).unwrap();
+
+        let mut cursor = db.new_cursor(&txn).unwrap();
+        assert!(cursor.current_sizes().is_err());
+        cursor.move_to_first().unwrap();
+        assert_eq!(cursor.current_sizes().unwrap(), (1, 100));
+        cursor.move_to_key(&"bcd").unwrap();
+        assert_eq!(cursor.current_sizes().unwrap(), (3, 4));
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_contains_keys() {
+    let env = EnvBuilder::new().open(&next_path(), USER_DIR).unwrap();
+    let db = env.get_default_db(DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        for key in &["b", "d", "f"] {
+            db.set(key, &"", &txn).unwrap();
+        }
+
+        let keys = ["a", "b", "c", "d", "f", "g"];
+        assert_eq!(db.contains_keys(&keys, &txn).unwrap(), vec![false, true, false, true, true, false]);
+        let unsorted = ["f", "zz", "b", "b"];
+        assert_eq!(db.contains_keys(&unsorted, &txn).unwrap(), vec![true, false, true, true]);
+        assert!(db.contains_keys::<&str>(&[], &txn).unwrap().is_empty());
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_db_flush() {
+    let path = next_path();
+    let env = EnvBuilder::new().max_dbs(1).flags(environment::ENV_CREATE_NO_SYNC).open(&path, USER_DIR).unwrap();
+    let db = env.create_db("events", DbFlags::empty()).unwrap();
+    let txn = env.new_transaction().unwrap();
+    db.set(&"key", &"value", &txn).unwrap();
+    txn.commit().unwrap();
+
+    let reader = env.get_reader().unwrap();
+    db.flush(&reader).unwrap();
+    drop(reader);
+
+    // flush is environment wide, so it fails like sync does
+    let readonly = EnvBuilder::new().max_dbs(1).flags(environment::ENV_CREATE_READONLY).open(&path, USER_DIR).unwrap();
+    let db = readonly.get_db("events", DbFlags::empty()).unwrap();
+    let reader = readonly.get_reader().unwrap();
+    assert!(db.flush(&reader).is_err());
+}
+
+#[test]
+fn test_require_existing_dir() {
+    let path = next_path();
+    let res = EnvBuilder::new().require_existing_dir(true).open(&path, USER_DIR);
+    assert!(matches!(res, Err(MdbError::InvalidPath)));
+    assert!(!path.exists());
+
+    let res = EnvBuilder::new().autocreate_dir(true).require_existing_dir(true).open(&path, USER_DIR);
+    assert!(matches!(res, Err(MdbError::InvalidPath)));
+
+    fs::create_dir_all(&path).unwrap();
+    let env = EnvBuilder::new().require_existing_dir(true).open(&path, USER_DIR).unwrap();
+    assert!(env.get_default_db(DbFlags::empty()).is_ok());
+}
+
+#[test]
+fn test_cursor_iterator_count() {
+    let env = EnvBuilder::new().max_dbs(2).open(&next_path(), USER_DIR).unwrap();
+    let db = env.create_db("ints", database::DB_INT_KEY).unwrap();
+    let dups = env.create_db("dups", database::DB_ALLOW_DUPS).unwrap();
+    let txn = env.new_transaction().unwrap();
+    {
+        for i in 0..10_000u64 {
+            db.set(&i, &vec![0u8; 64], &txn).unwrap();
+        }
+        for i in 0..50u32 {
+            dups.set(&"key", &i, &txn).unwrap();
+        }
+
+        let (start, end) = (1000u64, 8000u64);
+        assert_eq!(db.iter(&txn).unwrap().count(), Iterator::count(db.iter(&txn).unwrap()));
+        assert_eq!(db.keyrange(&start, &end, &txn).unwrap().count(), 7001);
+        assert_eq!(db.keyrange(&start, &end, &txn).unwrap().count(),
+                   Iterator::count(db.keyrange(&start, &end, &txn).unwrap()));
+        assert_eq!(db.keyrange_from(&start, &txn).unwrap().count(),
+                   Iterator::count(db.keyrange_from(&start, &txn).unwrap()));
+        assert_eq!(db.keyrange_to(&end, &txn).unwrap().count(),
+                   Iterator::count(db.keyrange_to(&end, &txn).unwrap()));
+        assert_eq!(db.range(start..end, &txn).unwrap().count(), 7000);
+        assert_eq!(dups.item_iter(&"key", &txn).unwrap().count(), 50);
+
+        // counts only what is left
+        let mut iter = db.keyrange(&start, &end, &txn).unwrap();
+        iter.nth(10);
+        assert_eq!(iter.count(), 6990);
+        assert_eq!(db.keyrange_from(&20_000u64, &txn).unwrap().count(), 0);
+    }
+    txn.commit().unwrap();
+}
@@ -13,9 +13,10 @@
 //! this, but unfortunately there is no way yet.
 
 
-use std::{slice};
+use std::{mem, ptr, slice};
+use std::num::{NonZeroU32, NonZeroU64, NonZeroU128};
 
-use crate::core::MdbValue;
+use crate::core::{MdbError, MdbResult, MdbValue};
 use ffi::MDB_val;
 
 /// `ToMdbValue` is supposed to convert a value to a memory
@@ -34,6 +35,22 @@ pub trait FromMdbValue {
     fn from_mdb_value(value: &MdbValue) -> Self;
 }
 
+/// `TryFromMdbValue` is a checked counterpart of `FromMdbValue`
+/// which validates stored data before reconstructing a value
+/// and reports a `Conversion` error instead of misreading it.
+pub trait TryFromMdbValue: Sized {
+    fn try_from_mdb_value(value: &MdbValue) -> MdbResult<Self>;
+}
+
+fn check_size<T>(value: &MdbValue) -> MdbResult<()> {
+    let expected = mem::size_of::<T>();
+    if value.get_size() == expected {
+        Ok(())
+    } else {
+        Err(MdbError::Conversion(format!("expected {} bytes, found {}", expected, value.get_size())))
+    }
+}
+
 impl ToMdbValue for Vec<u8> {
     fn to_mdb_value(&self) -> MdbValue {
         unsafe {
@@ -103,6 +120,33 @@ impl FromMdbValue for Vec<u8> {
     }
 }
 
+#[cfg(feature = "bytes")]
+impl ToMdbValue for bytes::Bytes {
+    fn to_mdb_value(&self) -> MdbValue {
+        unsafe {
+            MdbValue::new(self.as_ptr() as *const libc::c_void, self.len())
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl<'a> ToMdbValue for &'a bytes::Bytes {
+    fn to_mdb_value(&self) -> MdbValue {
+        (**self).to_mdb_value()
+    }
+}
+
+/// Value is copied, as memory of LMDB can't outlive transaction
+#[cfg(feature = "bytes")]
+impl FromMdbValue for bytes::Bytes {
+    fn from_mdb_value(value: &MdbValue) -> bytes::Bytes {
+        unsafe {
+            let ptr = value.get_ref() as *const u8;
+            bytes::Bytes::copy_from_slice(slice::from_raw_parts(ptr, value.get_size()))
+        }
+    }
+}
+
 impl FromMdbValue for () {
     fn from_mdb_value(_: &MdbValue) {
     }
@@ -124,23 +168,88 @@ impl<'b> FromMdbValue for &'b [u8] {
     }
 }
 
+impl<const N: usize> ToMdbValue for [u8; N] {
+    fn to_mdb_value(&self) -> MdbValue<'_> {
+        unsafe {
+            MdbValue::new(self.as_ptr() as *const libc::c_void, N)
+        }
+    }
+}
+
+/// Panics if stored value isn't exactly N bytes long, use
+/// `TryFromMdbValue` to get an error instead
+impl<const N: usize> FromMdbValue for [u8; N] {
+    fn from_mdb_value(value: &MdbValue) -> [u8; N] {
+        match TryFromMdbValue::try_from_mdb_value(value) {
+            Ok(arr) => arr,
+            Err(_) => panic!("expected {} bytes for [u8; {}], found {}", N, N, value.get_size()),
+        }
+    }
+}
+
+impl<const N: usize> TryFromMdbValue for [u8; N] {
+    fn try_from_mdb_value(value: &MdbValue) -> MdbResult<[u8; N]> {
+        check_size::<[u8; N]>(value)?;
+        let mut arr = [0u8; N];
+        unsafe {
+            ptr::copy_nonoverlapping(value.get_ref() as *const u8, arr.as_mut_ptr(), N);
+        }
+        Ok(arr)
+    }
+}
+
 macro_rules! mdb_for_primitive {
     ($t:ty) => (
         impl ToMdbValue for $t {
-            fn to_mdb_value(&self) -> MdbValue {
-                MdbValue::new_from_sized(self)
+            fn to_mdb_value(&self) -> MdbValue<'_> {
+                MdbValue::from_pod(self)
             }
         }
 
         impl FromMdbValue for $t {
             fn from_mdb_value(value: &MdbValue) -> $t {
                 unsafe {
-                    let t: *mut $t = value.get_ref() as *mut $t;
-                    *t
+                    // data in pages isn't guaranteed to be aligned
+                    let t: *const $t = value.get_ref() as *const $t;
+                    ptr::read_unaligned(t)
                 }
             }
         }
 
+        impl TryFromMdbValue for $t {
+            fn try_from_mdb_value(value: &MdbValue) -> MdbResult<$t> {
+                check_size::<$t>(value)?;
+                Ok(FromMdbValue::from_mdb_value(value))
+            }
+        }
+
+    )
+}
+
+macro_rules! mdb_for_nonzero {
+    ($t:ty, $inner:ty) => (
+        impl ToMdbValue for $t {
+            fn to_mdb_value(&self) -> MdbValue<'_> {
+                // NonZero integers have the same layout as their inner type
+                MdbValue::from_pod(self)
+            }
+        }
+
+        impl FromMdbValue for $t {
+            fn from_mdb_value(value: &MdbValue) -> $t {
+                let inner: $inner = FromMdbValue::from_mdb_value(value);
+                <$t>::new(inner).expect("stored value of a non-zero integer is zero")
+            }
+        }
+
+        impl TryFromMdbValue for $t {
+            fn try_from_mdb_value(value: &MdbValue) -> MdbResult<$t> {
+                let inner: $inner = TryFromMdbValue::try_from_mdb_value(value)?;
+                <$t>::new(inner).ok_or_else(|| {
+                    MdbError::Conversion(format!("stored value of {} is zero", stringify!($t)))
+                })
+            }
+        }
     )
 }
 
@@ -152,6 +261,41 @@ mdb_for_primitive!(u32);
 mdb_for_primitive!(i32);
 mdb_for_primitive!(u64);
 mdb_for_primitive!(i64);
+mdb_for_primitive!(u128);
+mdb_for_primitive!(i128);
 mdb_for_primitive!(f32);
 mdb_for_primitive!(f64);
-mdb_for_primitive!(bool);
+
+impl ToMdbValue for bool {
+    fn to_mdb_value(&self) -> MdbValue<'_> {
+        MdbValue::from_pod(self)
+    }
+}
+
+/// Panics if stored byte is neither 0 nor 1, use `TryFromMdbValue`
+/// to get an error instead
+impl FromMdbValue for bool {
+    fn from_mdb_value(value: &MdbValue) -> bool {
+        match TryFromMdbValue::try_from_mdb_value(value) {
+            Ok(b) => b,
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+impl TryFromMdbValue for bool {
+    fn try_from_mdb_value(value: &MdbValue) -> MdbResult<bool> {
+        check_size::<bool>(value)?;
+        // any byte other than 0 or 1 isn't a valid bool, so it is
+        // read as u8 first
+        match u8::from_mdb_value(value) {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(MdbError::Conversion(format!("stored byte {} isn't a valid bool", b))),
+        }
+    }
+}
+
+mdb_for_nonzero!(NonZeroU32, u32);
+mdb_for_nonzero!(NonZeroU64, u64);
+mdb_for_nonzero!(NonZeroU128, u128);
@@ -1,5 +1,7 @@
 use libc::{c_uint};
+use std::cell::{Cell, RefCell};
 use std::ptr;
+use std::rc::Rc;
 
 use ffi::{self};
 
@@ -15,22 +17,31 @@ pub enum TransactionState {
     Invalid,  // Invalid, no further operation possible
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct NativeTransaction<'a> {
     pub handle: *mut ffi::MDB_txn,
     pub env: &'a Environment,
     flags: usize,
     pub state: TransactionState,
+    // ~ counters are shared by clones of transaction
+    writes: Rc<Cell<usize>>,
+    bytes_written: Rc<Cell<usize>>,
+    write_limit: Option<usize>,
 }
 
 impl<'a> NativeTransaction<'a> {
     pub fn new_with_handle(h: *mut ffi::MDB_txn, flags: usize, env: &Environment) -> NativeTransaction {
         // debug!("new native txn");
+        // new transaction starts in Normal state
+        env.txn_started();
         NativeTransaction {
             handle: h,
             flags,
             state: TransactionState::Normal,
             env,
+            writes: Rc::new(Cell::new(0)),
+            bytes_written: Rc::new(Cell::new(0)),
+            write_limit: None,
         }
     }
 
@@ -41,11 +52,12 @@ impl<'a> NativeTransaction<'a> {
     pub fn commit(&mut self) -> MdbResult<()> {
         assert_state_eq!(txn, self.state, TransactionState::Normal);
         // debug!("commit txn");
-        self.state = if self.is_readonly() {
+        let state = if self.is_readonly() {
             TransactionState::Released
         } else {
             TransactionState::Invalid
         };
+        self.set_state(state);
         try_mdb!(unsafe { ffi::mdb_txn_commit(self.handle) } );
         Ok(())
     }
@@ -56,11 +68,12 @@ impl<'a> NativeTransaction<'a> {
         } else {
             // debug!("abort txn");
             unsafe { ffi::mdb_txn_abort(self.handle); }
-            self.state = if self.is_readonly() {
+            let state = if self.is_readonly() {
                 TransactionState::Released
             } else {
                 TransactionState::Invalid
             };
+            self.set_state(state);
         }
     }
 
@@ -71,7 +84,7 @@ impl<'a> NativeTransaction<'a> {
             debug!("Can't reset transaction: current state {:?}", self.state);
         } else {
             unsafe { ffi::mdb_txn_reset(self.handle); }
-            self.state = TransactionState::Released;
+            self.set_state(TransactionState::Released);
         }
     }
 
@@ -79,27 +92,80 @@ impl<'a> NativeTransaction<'a> {
     fn renew(&mut self) -> MdbResult<()> {
         assert_state_eq!(txn, self.state, TransactionState::Released);
         try_mdb!(unsafe {ffi::mdb_txn_renew(self.handle)});
-        self.state = TransactionState::Normal;
+        self.set_state(TransactionState::Normal);
         Ok(())
     }
 
     fn new_child(&self, flags: c_uint) -> MdbResult<NativeTransaction> {
+        if self.is_readonly() {
+            return Err(StateError("transactions can't be nested in a read-only one".to_owned()));
+        }
         let mut out: *mut ffi::MDB_txn = ptr::null_mut();
         try_mdb!(unsafe { ffi::mdb_txn_begin(ffi::mdb_txn_env(self.handle), self.handle, flags, &mut out) });
         Ok(NativeTransaction::new_with_handle(out, flags as usize, self.env))
     }
 
+    /// Fails with `WriteLimit` if writing len more bytes would
+    /// exceed soft write limit of transaction
+    pub(crate) fn check_write_limit(&self, len: usize) -> MdbResult<()> {
+        match self.write_limit {
+            Some(limit) if self.bytes_written.get() + len > limit => {
+                let msg = format!("write limit of {} bytes reached: {} bytes written, {} more requested",
+                                  limit, self.bytes_written.get(), len);
+                Err(MdbError::WriteLimit(msg))
+            },
+            _ => Ok(())
+        }
+    }
+
+    /// Registers a successful put or delete of len key and value
+    /// bytes, len is 0 for deletes
+    pub(crate) fn record_write(&self, len: usize) {
+        self.writes.set(self.writes.get() + 1);
+        self.bytes_written.set(self.bytes_written.get() + len);
+    }
+
     /// Used in Drop to switch state
     fn silent_abort(&mut self) {
         if self.state == TransactionState::Normal {
             // debug!("silent abort");
             unsafe {ffi::mdb_txn_abort(self.handle);}
-            self.state = TransactionState::Invalid;
+            self.set_state(TransactionState::Invalid);
+        }
+    }
+
+    /// Switches state keeping count of active transactions in
+    /// environment, only transactions in Normal state are counted
+    fn set_state(&mut self, state: TransactionState) {
+        let was_active = self.state == TransactionState::Normal;
+        let is_active = state == TransactionState::Normal;
+        if is_active && !was_active {
+            self.env.txn_started();
+        } else if was_active && !is_active {
+            self.env.txn_finished();
         }
+        self.state = state;
     }
 
 }
 
+impl<'a> Clone for NativeTransaction<'a> {
+    fn clone(&self) -> NativeTransaction<'a> {
+        if self.state == TransactionState::Normal {
+            self.env.txn_started();
+        }
+        NativeTransaction {
+            handle: self.handle,
+            env: self.env,
+            flags: self.flags,
+            state: self.state,
+            writes: Rc::clone(&self.writes),
+            bytes_written: Rc::clone(&self.bytes_written),
+            write_limit: self.write_limit,
+        }
+    }
+}
+
 impl<'a> Drop for NativeTransaction<'a> {
     fn drop(&mut self) {
         //debug!("Dropping native transaction!");
@@ -112,6 +178,33 @@ pub trait Txn<'a>: std::fmt::Debug {
     fn get_handle(&self) -> *mut ffi::MDB_txn;
     fn get_env(&self) -> &'a Environment;
     fn get_state(&self) -> TransactionState;
+    /// Transactions which don't expose native one are treated as
+    /// read-write
+    fn is_readonly(&self) -> bool {
+        self.get_native().is_some_and(|txn| txn.is_readonly())
+    }
+    /// Native transaction backing this one. Writes are counted and
+    /// write limit is checked only for transactions which expose it
+    fn get_native(&self) -> Option<&NativeTransaction<'a>> {
+        None
+    }
+    /// Number of successful puts and deletes done in transaction.
+    /// `Cursor::add_items` counts as one write. LMDB doesn't expose
+    /// dirty pages, so it's only a rough hint of transaction size
+    fn write_count(&self) -> usize {
+        self.get_native().map_or(0, |txn| txn.writes.get())
+    }
+    /// Approximate number of pages dirtied by transaction. LMDB
+    /// doesn't expose it, so it's an upper bound counting successful
+    /// puts and deletes, same as [write_count](#method.write_count)
+    fn dirty_pages(&self) -> usize {
+        self.write_count()
+    }
+    /// Id of transaction, i.e. of snapshot seen by a reader. A reader
+    /// is stale if its id is less than `Environment::last_txnid`
+    fn id(&self) -> u64 {
+        unsafe { ffi::mdb_txn_id(self.get_handle()) as u64 }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -132,6 +225,12 @@ impl<'a> Txn<'a> for Transaction<'a> {
     fn get_state(&self) -> TransactionState {
         self.inner.state
     }
+    fn is_readonly(&self) -> bool {
+        self.inner.is_readonly()
+    }
+    fn get_native(&self) -> Option<&NativeTransaction<'a>> {
+        Some(&self.inner)
+    }
 }
 
 impl<'a> Transaction<'a> {
@@ -141,7 +240,13 @@ impl<'a> Transaction<'a> {
         }
     }
 
+    /// Begins a nested read-write transaction. Fails with
+    /// `StateError` if environment uses a writable map, as LMDB
+    /// doesn't support nesting with `ENV_CREATE_WRITE_MAP`
     pub fn new_child(&self) -> MdbResult<Transaction> {
+        if self.inner.env.is_write_map() {
+            return Err(StateError("nested transactions aren't supported by environment opened with ENV_CREATE_WRITE_MAP".to_owned()));
+        }
         self.inner.new_child(0)
             .and_then(|txn| Ok(Transaction::new_with_native(txn)))
     }
@@ -151,6 +256,19 @@ impl<'a> Transaction<'a> {
             .and_then(|txn| Ok(ReadonlyTransaction::new_with_native(txn)))
     }
 
+    /// Total size of keys and values put in this transaction
+    pub fn bytes_written(&self) -> usize {
+        self.inner.bytes_written.get()
+    }
+
+    /// Sets a soft limit on bytes written by transaction. Once it is
+    /// reached, puts fail with `WriteLimit` error before
+    /// touching database, so transaction could be committed and work
+    /// continued in a new one
+    pub fn set_write_limit(&mut self, limit: usize) {
+        self.inner.write_limit = Some(limit);
+    }
+
     /// Commits transaction, moves it out
     pub fn commit(self) -> MdbResult<()> {
         //self.inner.commit()
@@ -188,7 +306,12 @@ impl<'a> Txn<'a> for ReadonlyTransaction<'a> {
     fn get_state(&self) -> TransactionState {
         self.inner.state
     }
-
+    fn is_readonly(&self) -> bool {
+        self.inner.is_readonly()
+    }
+    fn get_native(&self) -> Option<&NativeTransaction<'a>> {
+        Some(&self.inner)
+    }
 }
 
 impl<'a> ReadonlyTransaction<'a> {
@@ -198,6 +321,8 @@ impl<'a> ReadonlyTransaction<'a> {
         }
     }
 
+    /// LMDB doesn't nest transactions in read-only ones, so it
+    /// always fails with `StateError`
     pub fn new_ro_child(&self) -> MdbResult<ReadonlyTransaction> {
         self.inner.new_child(ffi::MDB_RDONLY)
             .and_then(|txn| Ok(ReadonlyTransaction::new_with_native(txn)))
@@ -225,4 +350,92 @@ impl<'a> ReadonlyTransaction<'a> {
     pub fn bind(&self, db_handle: DbHandle) -> Database {
         Database::new_with_handle(db_handle.handle)
     }
-}
\ No newline at end of file
+
+    /// Aborts reader and begins a read-write transaction instead.
+    /// It isn't atomic: new transaction sees latest committed data,
+    /// which may differ from snapshot seen by reader, so anything
+    /// read before should be read again. Fails with `StateError` if
+    /// environment is read-only
+    pub fn upgrade(self) -> MdbResult<Transaction<'a>> {
+        let env = self.inner.env;
+        drop(self);
+        env.new_transaction()
+    }
+
+    /// Resets transaction and moves it into a guard, which doesn't
+    /// allow any reads until transaction is renewed
+    pub fn into_reset(self) -> ResetReader<'a> {
+        let mut txn = self;
+        txn.reset();
+        ResetReader { txn }
+    }
+}
+
+/// Read only transaction released by `into_reset`. Call `renew` to
+/// get a usable transaction back
+#[derive(Debug)]
+pub struct ResetReader<'a> {
+    txn: ReadonlyTransaction<'a>,
+}
+
+impl<'a> ResetReader<'a> {
+    /// Acquires a new reader lock and returns transaction
+    pub fn renew(self) -> MdbResult<ReadonlyTransaction<'a>> {
+        let mut txn = self.txn;
+        txn.renew()?;
+        Ok(txn)
+    }
+}
+
+/// Keeps reset readers to reuse them instead of beginning a new
+/// reader for each short read. Readers are tied to a thread unless
+/// environment is opened with `ENV_CREATE_NO_TLS`, so the pool
+/// should be used from one thread otherwise
+#[derive(Debug)]
+pub struct ReaderPool<'a> {
+    env: &'a Environment,
+    readers: RefCell<Vec<ReadonlyTransaction<'a>>>,
+}
+
+impl<'a> ReaderPool<'a> {
+    pub fn new(env: &'a Environment) -> ReaderPool<'a> {
+        ReaderPool {
+            env,
+            readers: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Runs f with a renewed pooled reader, or with a new one if pool
+    /// is empty. Reader is reset and put back into pool afterwards
+    pub fn with_reader<F, R>(&self, f: F) -> MdbResult<R>
+        where F: FnOnce(&ReadonlyTransaction<'a>) -> R {
+        let pooled = self.readers.borrow_mut().pop();
+        let reader = match pooled {
+            Some(mut reader) => {
+                reader.renew()?;
+                reader
+            },
+            None => self.env.get_reader()?,
+        };
+
+        let res = f(&reader);
+        self.add(reader);
+        Ok(res)
+    }
+
+    /// Resets reader and adds it to pool
+    pub fn add(&self, reader: ReadonlyTransaction<'a>) {
+        let mut reader = reader;
+        reader.reset();
+        self.readers.borrow_mut().push(reader);
+    }
+
+    /// Number of idle readers in pool
+    pub fn len(&self) -> usize {
+        self.readers.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.readers.borrow().is_empty()
+    }
+}
@@ -0,0 +1,93 @@
+//! Queue of writes drained by a single background writer
+//!
+//! LMDB allows only one write transaction at a time, so instead of
+//! contending for it from many threads writes could be enqueued
+//! through `WriteSender` and applied by a `WriterHandle`'s thread,
+//! which batches everything queued so far into a single transaction.
+
+use std::borrow::ToOwned;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use crate::core::{MdbError, MdbResult};
+use crate::database::{Database, DbFlags};
+use crate::environment::Environment;
+
+/// Max number of writes committed in one transaction
+const MAX_BATCH_SIZE: usize = 1000;
+
+type WriteRequest = (String, Vec<u8>, Vec<u8>);
+
+/// Sending side of writer queue, can be cloned and sent to other threads
+#[derive(Clone, Debug)]
+pub struct WriteSender {
+    tx: Sender<WriteRequest>,
+}
+
+impl WriteSender {
+    /// Enqueues write of key/value into named database, which is
+    /// created if it doesn't exist. Fails if writer has stopped
+    pub fn send(&self, db_name: &str, key: Vec<u8>, value: Vec<u8>) -> MdbResult<()> {
+        self.tx.send((db_name.to_owned(), key, value))
+            .map_err(|_| MdbError::StateError("writer queue is closed".to_owned()))
+    }
+}
+
+/// Handle of background writer thread
+#[derive(Debug)]
+pub struct WriterHandle {
+    thread: JoinHandle<MdbResult<usize>>,
+}
+
+impl WriterHandle {
+    /// Waits until all senders are dropped and queue is drained.
+    /// Returns number of written entries or the first error
+    /// which stopped the writer
+    pub fn join(self) -> MdbResult<usize> {
+        match self.thread.join() {
+            Ok(res) => res,
+            Err(_) => Err(MdbError::StateError("writer thread panicked".to_owned())),
+        }
+    }
+}
+
+pub(crate) fn writer_queue(env: Environment) -> (WriteSender, WriterHandle) {
+    let (tx, rx) = mpsc::channel();
+    let thread = thread::spawn(move || drain(&env, rx));
+    (WriteSender { tx }, WriterHandle { thread })
+}
+
+fn drain(env: &Environment, rx: Receiver<WriteRequest>) -> MdbResult<usize> {
+    let mut dbs: HashMap<String, Database> = HashMap::new();
+    let mut written = 0;
+
+    // blocks until next write arrives, ends once all senders are gone
+    while let Ok(first) = rx.recv() {
+        let mut batch = vec![first];
+        while batch.len() < MAX_BATCH_SIZE {
+            match rx.try_recv() {
+                Ok(req) => batch.push(req),
+                Err(_) => break,
+            }
+        }
+
+        // dbs have to be opened before starting transaction as
+        // opening uses its own one
+        for (name, _, _) in batch.iter() {
+            if !dbs.contains_key(name) {
+                let db = env.create_db(name, DbFlags::empty())?;
+                dbs.insert(name.clone(), db);
+            }
+        }
+
+        let txn = env.new_transaction()?;
+        for (name, key, value) in batch.iter() {
+            dbs[name].set(key, value, &txn)?;
+        }
+        txn.commit()?;
+        written += batch.len();
+    }
+
+    Ok(written)
+}